@@ -1,9 +1,132 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::data::resource_record::ResourceRecord;
+
 pub(crate) struct ServerConfig {
-    pub port: u16,
+    /// Addresses to serve DNS on, each with its own listeners
+    pub listen: Vec<ListenAddress>,
+    /// Answer for CHAOS TXT queries of version.bind, refused if not set
+    pub version: Option<String>,
+    /// Answer for CHAOS TXT queries of hostname.bind, refused if not set
+    pub hostname: Option<String>,
+    /// Records answered locally instead of asking upstream
+    pub records: Vec<ResourceRecord>,
+    /// Certificate for encrypted transports, required if any is enabled
+    pub tls: Option<TlsConfig>,
+    /// Connections of any transport without requests for this long are closed
+    pub idle_timeout: Duration,
+    /// Connections beyond this limit are closed right after accepting them,
+    /// counted separately per listener
+    pub max_connections: usize,
+    /// Requests of a single stream connection handled at the same time,
+    /// further requests are only read once one of them is answered
+    pub max_pending_requests: usize,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
-        Self { port: 5353 }
+        Self {
+            listen: vec![
+                ListenAddress::new(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 5353))),
+                ListenAddress::new(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 5353))),
+            ],
+            version: Some(format!("dns-server {}", env!("CARGO_PKG_VERSION"))),
+            hostname: None,
+            records: Vec::new(),
+            tls: None,
+            idle_timeout: Duration::from_secs(10),
+            max_connections: 256,
+            max_pending_requests: 32,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Enables the encrypted transports with the given certificate on their
+    /// default ports of all addresses.
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self.listen.extend([
+            ListenAddress::tls(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 853))),
+            ListenAddress::tls(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 853))),
+            ListenAddress::quic(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 853))),
+            ListenAddress::quic(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 853))),
+            ListenAddress::https(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 443))),
+            ListenAddress::https(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 443))),
+        ]);
+        self
+    }
+}
+
+/// Address to listen on and the transports served there
+#[derive(Debug, Clone)]
+pub(crate) struct ListenAddress {
+    pub address: SocketAddr,
+    pub udp: bool,
+    pub tcp: bool,
+    /// DNS over TLS (RFC 7858), usually on port 853
+    pub tls: bool,
+    /// DNS over HTTPS (RFC 8484), usually on port 443
+    pub https: bool,
+    /// DNS over QUIC (RFC 9250), usually on UDP port 853
+    pub quic: bool,
+}
+
+impl ListenAddress {
+    /// Serves both UDP and TCP on `address`
+    pub fn new(address: SocketAddr) -> Self {
+        Self {
+            address,
+            udp: true,
+            tcp: true,
+            tls: false,
+            https: false,
+            quic: false,
+        }
+    }
+
+    /// Serves only DNS over TLS on `address`
+    pub fn tls(address: SocketAddr) -> Self {
+        Self {
+            address,
+            udp: false,
+            tcp: false,
+            tls: true,
+            https: false,
+            quic: false,
+        }
+    }
+
+    /// Serves only DNS over HTTPS on `address`
+    pub fn https(address: SocketAddr) -> Self {
+        Self {
+            address,
+            udp: false,
+            tcp: false,
+            tls: false,
+            https: true,
+            quic: false,
+        }
     }
+
+    /// Serves only DNS over QUIC on `address`
+    pub fn quic(address: SocketAddr) -> Self {
+        Self {
+            address,
+            udp: false,
+            tcp: false,
+            tls: false,
+            https: false,
+            quic: true,
+        }
+    }
+}
+
+/// Certificate chain and private key as PEM files
+#[derive(Debug, Clone)]
+pub(crate) struct TlsConfig {
+    pub certificate_path: PathBuf,
+    pub key_path: PathBuf,
 }
@@ -1,46 +1,231 @@
-use anyhow::Context;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Write};
+
+use anyhow::bail;
 use bytes::{BufMut, BytesMut};
 
+use crate::data::error::ParseError;
+
+/// Maximum size of a single label, the upper two bits of the length
+/// are reserved for compression pointers.
+const MAX_LABEL_SIZE: usize = 63;
+/// Maximum size of a name in its binary form, including length bytes.
+const MAX_NAME_SIZE: usize = 255;
+/// Compression pointers only have 14 bits to encode an offset.
+const MAX_POINTER_OFFSET: usize = 0b0011_1111_1111_1111;
+const POINTER_MASK: u16 = 0b1100_0000_0000_0000;
+
+/// NameCompression remembers at which offsets of a message names have been
+/// written, so that later names sharing a suffix can point to it instead of
+/// repeating its labels. Suffixes are matched exactly, including their case.
+#[derive(Default)]
+pub(crate) struct NameCompression {
+    offsets: HashMap<Vec<u8>, u16>,
+}
+
 pub(crate) struct DomainName<'a> {
-    parts: Vec<&'a [u8]>,
+    parts: Vec<Cow<'a, [u8]>>,
 }
 
 impl<'a> DomainName<'a> {
-    pub(crate) fn to_string(&self) -> anyhow::Result<String> {
-        // Start at length 32 to avoid the first couple re-allocations
-        let mut bytes = BytesMut::with_capacity(32);
-        for part in &self.parts[..self.parts.len() - 1] {
-            bytes.put_slice(part);
-            bytes.put_u8(b'.');
+    /// Splits a domain name in its textual form into its labels. A single
+    /// trailing dot is accepted, the root domain is the empty string. Within
+    /// a label, `\X` stands for the character X and `\DDD` for the byte with
+    /// the decimal value DDD (RFC 1035 5.1).
+    pub(crate) fn try_from_str(name: &'a str) -> anyhow::Result<DomainName<'a>> {
+        let mut parts = Vec::new();
+        if name != "." {
+            let mut part = Vec::new();
+            let mut bytes = name.bytes();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'.' => parts.push(std::mem::take(&mut part)),
+                    b'\\' => {
+                        let Some(escaped) = bytes.next() else {
+                            bail!("Domain name {} ends with an escape", name);
+                        };
+                        if escaped.is_ascii_digit() {
+                            let digits = [
+                                escaped,
+                                bytes.next().unwrap_or(0),
+                                bytes.next().unwrap_or(0),
+                            ];
+                            let Some(value) = std::str::from_utf8(&digits)
+                                .ok()
+                                .and_then(|digits| digits.parse().ok())
+                            else {
+                                bail!("Domain name {} contains an invalid escape", name);
+                            };
+                            part.push(value);
+                        } else {
+                            part.push(escaped);
+                        }
+                    }
+                    _ => part.push(byte),
+                }
+            }
+            if !part.is_empty() || parts.is_empty() && !name.is_empty() {
+                parts.push(part);
+            }
+        }
+
+        if parts
+            .iter()
+            .any(|part| part.is_empty() || part.len() > MAX_LABEL_SIZE)
+        {
+            bail!("Domain name {} contains a label of invalid length", name);
+        }
+        let domain_name = Self {
+            parts: parts.into_iter().map(Cow::Owned).collect(),
+        };
+        if domain_name.binary_size() > MAX_NAME_SIZE {
+            bail!("Domain name {} is too long", name);
+        }
+        Ok(domain_name)
+    }
+
+    /// Writes the name as uncompressed sequence of labels.
+    pub(crate) fn write_as_bytes(&self, output: &mut BytesMut) {
+        for part in &self.parts {
+            output.put_u8(part.len() as u8);
+            output.put_slice(part);
+        }
+        output.put_u8(0);
+    }
+
+    /// Writes the name, replacing the longest suffix that has already been
+    /// written to `output` with a pointer to it. `output` has to contain the
+    /// whole message written so far, as pointers are offsets from its start.
+    pub(crate) fn write_compressed(
+        &self,
+        output: &mut BytesMut,
+        compression: &mut NameCompression,
+    ) {
+        for i in 0..self.parts.len() {
+            let mut suffix = BytesMut::new();
+            Self {
+                parts: self.parts[i..].to_vec(),
+            }
+            .write_as_bytes(&mut suffix);
+            let suffix = suffix.to_vec();
+
+            if let Some(offset) = compression.offsets.get(&suffix) {
+                output.put_u16(POINTER_MASK | offset);
+                return;
+            }
+            if output.len() <= MAX_POINTER_OFFSET {
+                compression.offsets.insert(suffix, output.len() as u16);
+            }
+            output.put_u8(self.parts[i].len() as u8);
+            output.put_slice(&self.parts[i]);
         }
-        bytes.put_slice(self.parts[self.parts.len() - 1]);
+        output.put_u8(0);
+    }
+
+    fn binary_size(&self) -> usize {
+        self.parts.iter().map(|part| part.len() + 1).sum::<usize>() + 1
+    }
 
-        let as_str = String::from_utf8(bytes.to_vec())
-            .context("Domain name contained broken utf-8 sequences")?;
-        return Ok(as_str.to_lowercase());
+    #[cfg(test)]
+    pub(crate) fn try_from(bytes: &'a [u8]) -> Result<(usize, DomainName<'a>), ParseError> {
+        Self::try_from_message(bytes, 0)
     }
 
-    pub(crate) fn try_from(bytes: &'a [u8]) -> anyhow::Result<(usize, DomainName<'a>)> {
+    /// Reads the domain name starting at `offset` within a complete DNS
+    /// message. A name is a sequence of length-prefixed labels terminated
+    /// either by a zero-length label or by a compression pointer: two bytes
+    /// with the top bits set, whose remaining 14 bits are an offset into
+    /// `message` where the rest of the name continues (RFC 1035 4.1.4).
+    ///
+    /// Every pointer has to point before the labels it was reached from.
+    /// This rejects forward pointers and guarantees that following pointers
+    /// always terminates. Labels may not exceed 63 bytes, the whole name in
+    /// its uncompressed form may not exceed 255 bytes.
+    ///
+    /// The returned size is the number of bytes the name occupies at
+    /// `offset`, i.e. up to and including the first pointer.
+    pub(crate) fn try_from_message(
+        message: &'a [u8],
+        offset: usize,
+    ) -> Result<(usize, DomainName<'a>), ParseError> {
         let mut parts = Vec::new();
-        let mut binary_size: usize = 0;
+        let mut name_size = 1; // Terminating zero-length label
+        let mut position = offset;
+        let mut labels_start = offset;
+        let mut binary_size = None;
         loop {
-            let part_length = bytes[binary_size] as usize;
-            binary_size += 1;
-            if part_length == 0 {
-                break;
-            }
+            let part_length = *message.get(position).ok_or(ParseError::TruncatedMessage)?;
+            match part_length & 0b1100_0000 {
+                0b0000_0000 => {
+                    let part_length = part_length as usize;
+                    position += 1;
+                    if part_length == 0 {
+                        break;
+                    }
+
+                    name_size += part_length + 1;
+                    if name_size > MAX_NAME_SIZE {
+                        return Err(ParseError::NameTooLong);
+                    }
+                    let part = message
+                        .get(position..position + part_length)
+                        .ok_or(ParseError::TruncatedMessage)?;
+                    parts.push(Cow::Borrowed(part));
+                    position += part_length;
+                }
+                0b1100_0000 => {
+                    let pointer_low = *message
+                        .get(position + 1)
+                        .ok_or(ParseError::TruncatedMessage)?;
+                    let pointer = u16::from_be_bytes([part_length, pointer_low]);
+                    let target = (pointer & !POINTER_MASK) as usize;
+                    if target >= labels_start {
+                        return Err(ParseError::BadPointer);
+                    }
 
-            parts.push(&bytes[binary_size..binary_size + part_length]);
-            binary_size += part_length;
+                    binary_size.get_or_insert(position + 2 - offset);
+                    position = target;
+                    labels_start = target;
+                }
+                _ => return Err(ParseError::LabelTooLong),
+            }
         }
 
+        let binary_size = binary_size.unwrap_or_else(|| position - offset);
         Ok((binary_size, Self { parts }))
     }
 }
 
+/// Joins the labels with dots. Dots and backslashes within a label are
+/// escaped with a backslash, bytes outside of printable ASCII are written as
+/// `\DDD` (RFC 1035 5.1). So the text is split into the same labels again by
+/// [DomainName::try_from_str]. The original case is kept, so names can be
+/// echoed back unchanged, they have to be compared case-insensitively.
+impl Display for DomainName<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, part) in self.parts.iter().enumerate() {
+            if i > 0 {
+                f.write_char('.')?;
+            }
+            for &byte in part.iter() {
+                match byte {
+                    b'.' | b'\\' => write!(f, "\\{}", byte as char)?,
+                    0x21..=0x7e => f.write_char(byte as char)?,
+                    _ => write!(f, "\\{:03}", byte)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::data::domain_name::DomainName;
+    use bytes::BytesMut;
+
+    use crate::data::domain_name::{DomainName, NameCompression};
+    use crate::data::error::ParseError;
 
     #[test]
     fn parses_regular_domain_name() {
@@ -48,8 +233,8 @@ mod tests {
         let (length, domain_name) = DomainName::try_from(&bytes).unwrap();
         assert_eq!(8, length);
         assert_eq!(2, domain_name.parts.len());
-        assert_eq!(&[0x41, 0x41, 0x41], domain_name.parts[0]);
-        assert_eq!(&[0x42, 0x42], domain_name.parts[1]);
+        assert_eq!(&[0x41, 0x41, 0x41], &domain_name.parts[0][..]);
+        assert_eq!(&[0x42, 0x42], &domain_name.parts[1][..]);
     }
 
     #[test]
@@ -60,15 +245,214 @@ mod tests {
         let (length, domain_name) = DomainName::try_from(&bytes).unwrap();
         assert_eq!(8, length);
         assert_eq!(2, domain_name.parts.len());
-        assert_eq!(&[0x41, 0x41, 0x41], domain_name.parts[0]);
-        assert_eq!(&[0x42, 0x42], domain_name.parts[1]);
+        assert_eq!(&[0x41, 0x41, 0x41], &domain_name.parts[0][..]);
+        assert_eq!(&[0x42, 0x42], &domain_name.parts[1][..]);
+    }
+
+    #[test]
+    fn escapes_non_printable_bytes_in_domain_name() {
+        let bytes = [0x04, 0x41, 0x80, 0x20, 0x41, 0x02, 0x62, 0x62, 0x00];
+        let (_, domain_name) = DomainName::try_from(&bytes).unwrap();
+        let name = domain_name.to_string();
+        assert_eq!("A\\128\\032A.bb", name);
+
+        let mut round_trip = BytesMut::new();
+        DomainName::try_from_str(&name)
+            .unwrap()
+            .write_as_bytes(&mut round_trip);
+        assert_eq!(&bytes[..], &round_trip[..]);
+    }
+
+    #[test]
+    fn parses_root_domain_name() {
+        let bytes = [0x00];
+        let (length, domain_name) = DomainName::try_from(&bytes).unwrap();
+        assert_eq!(1, length);
+        assert_eq!("", domain_name.to_string());
+    }
+
+    #[test]
+    fn follows_compression_pointer() {
+        let bytes = [
+            0x02, 0x42, 0x42, 0x00, 0x03, 0x41, 0x41, 0x41, 0xc0, 0x00, 0x01, 0x02,
+        ];
+        let (length, domain_name) = DomainName::try_from_message(&bytes, 4).unwrap();
+        assert_eq!(6, length);
+        assert_eq!("AAA.BB", domain_name.to_string());
+    }
+
+    #[test]
+    fn follows_chained_compression_pointers() {
+        let bytes = [
+            0x02, 0x43, 0x43, 0x00, 0x02, 0x42, 0x42, 0xc0, 0x00, 0x01, 0x41, 0xc0, 0x04,
+        ];
+        let (length, domain_name) = DomainName::try_from_message(&bytes, 9).unwrap();
+        assert_eq!(4, length);
+        assert_eq!("A.BB.CC", domain_name.to_string());
+    }
+
+    #[test]
+    fn rejects_forward_compression_pointer() {
+        let bytes = [0x01, 0x41, 0xc0, 0x04, 0x01, 0x42, 0x00];
+        assert_eq!(
+            ParseError::BadPointer,
+            DomainName::try_from(&bytes).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_compression_pointer_loop() {
+        let bytes = [0x00, 0x00, 0x01, 0x41, 0xc0, 0x02];
+        assert_eq!(
+            ParseError::BadPointer,
+            DomainName::try_from_message(&bytes, 2).err().unwrap()
+        );
+
+        let bytes = [0x00, 0x00, 0x01, 0x41, 0xc0, 0x02, 0xc0, 0x04];
+        assert_eq!(
+            ParseError::BadPointer,
+            DomainName::try_from_message(&bytes, 6).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_domain_name() {
+        let bytes = [0x03, 0x41, 0x41];
+        assert_eq!(
+            ParseError::TruncatedMessage,
+            DomainName::try_from(&bytes).err().unwrap()
+        );
+
+        let bytes = [0x01, 0x41, 0xc0];
+        assert_eq!(
+            ParseError::TruncatedMessage,
+            DomainName::try_from(&bytes).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_too_long_domain_name() {
+        let mut bytes = Vec::new();
+        for _ in 0..4 {
+            bytes.push(63);
+            bytes.extend_from_slice(&[0x41; 63]);
+        }
+        bytes.push(0);
+        assert_eq!(
+            ParseError::NameTooLong,
+            DomainName::try_from(&bytes).err().unwrap()
+        );
+
+        // 3 * 64 + 62 + 1 = 255 bytes is just within the limit
+        bytes.truncate(3 * 64);
+        bytes.push(61);
+        bytes.extend_from_slice(&[0x41; 61]);
+        bytes.push(0);
+        assert!(DomainName::try_from(&bytes).is_ok());
     }
 
     #[test]
-    fn rejects_invalid_domain_name() {
-        let bytes = [0x03, 0x41, 0x80, 0x41, 0x00];
+    fn rejects_too_long_compressed_domain_name() {
+        let mut bytes = Vec::new();
+        for _ in 0..3 {
+            bytes.push(63);
+            bytes.extend_from_slice(&[0x41; 63]);
+        }
+        bytes.push(0);
+        bytes.extend_from_slice(&[63; 64]);
+        bytes.extend_from_slice(&[0xc0, 0x00]);
+        assert_eq!(
+            ParseError::NameTooLong,
+            DomainName::try_from_message(&bytes, 193).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn writes_domain_name_from_str() {
+        let mut bytes = BytesMut::new();
+        let domain_name = DomainName::try_from_str("aaa.bb.").unwrap();
+        domain_name.write_as_bytes(&mut bytes);
+        assert_eq!(
+            &[0x03, 0x61, 0x61, 0x61, 0x02, 0x62, 0x62, 0x00],
+            &bytes[..]
+        );
+
+        let mut bytes = BytesMut::new();
+        DomainName::try_from_str("")
+            .unwrap()
+            .write_as_bytes(&mut bytes);
+        assert_eq!(&[0x00], &bytes[..]);
+    }
+
+    #[test]
+    fn writes_compressed_domain_names() {
+        let mut bytes = BytesMut::from(&[0xff, 0xff][..]);
+        let mut compression = NameCompression::default();
+        DomainName::try_from_str("aaa.bb")
+            .unwrap()
+            .write_compressed(&mut bytes, &mut compression);
+        DomainName::try_from_str("c.aaa.bb")
+            .unwrap()
+            .write_compressed(&mut bytes, &mut compression);
+        DomainName::try_from_str("d.bb")
+            .unwrap()
+            .write_compressed(&mut bytes, &mut compression);
+        DomainName::try_from_str("d.bb")
+            .unwrap()
+            .write_compressed(&mut bytes, &mut compression);
+        assert_eq!(
+            &[
+                0xff, 0xff, 0x03, 0x61, 0x61, 0x61, 0x02, 0x62, 0x62, 0x00, 0x01, 0x63, 0xc0, 0x02,
+                0x01, 0x64, 0xc0, 0x06, 0xc0, 0x0e
+            ],
+            &bytes[..]
+        );
+
+        let (_, domain_name) = DomainName::try_from_message(&bytes, 14).unwrap();
+        assert_eq!("d.bb", domain_name.to_string());
+    }
+
+    #[test]
+    fn rejects_invalid_domain_name_str() {
+        assert!(DomainName::try_from_str("aaa..bb").is_err());
+        assert!(DomainName::try_from_str(&"a".repeat(64)).is_err());
+        assert!(DomainName::try_from_str(&["a"; 128].join(".")).is_err());
+    }
+
+    #[test]
+    fn escapes_dots_and_backslashes_in_labels() {
+        let bytes = [
+            0x03, 0x61, 0x2e, 0x62, 0x02, 0x63, 0x5c, 0x02, 0x64, 0x2e, 0x00,
+        ];
         let (_, domain_name) = DomainName::try_from(&bytes).unwrap();
-        let as_str = domain_name.to_string();
-        assert!(as_str.is_err());
+        let name = domain_name.to_string();
+        assert_eq!("a\\.b.c\\\\.d\\.", name);
+
+        let mut written = BytesMut::new();
+        DomainName::try_from_str(&name)
+            .unwrap()
+            .write_as_bytes(&mut written);
+        assert_eq!(&bytes[..], &written[..]);
+    }
+
+    #[test]
+    fn unescapes_decimal_bytes() {
+        let mut bytes = BytesMut::new();
+        DomainName::try_from_str("\\065\\.\\000.b")
+            .unwrap()
+            .write_as_bytes(&mut bytes);
+        assert_eq!(&[0x03, 0x41, 0x2e, 0x00, 0x01, 0x62, 0x00], &bytes[..]);
+
+        assert!(DomainName::try_from_str("a\\256").is_err());
+        assert!(DomainName::try_from_str("a\\").is_err());
+    }
+
+    #[test]
+    fn rejects_reserved_label_type() {
+        let bytes = [0x41, 0x00];
+        assert_eq!(
+            ParseError::LabelTooLong,
+            DomainName::try_from(&bytes).err().unwrap()
+        );
     }
 }
@@ -0,0 +1,413 @@
+use std::fmt::{Display, Formatter};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::data::error::ParseError;
+use crate::data::header::ResponseCode;
+use crate::data::rdata::{fmt_hex, RData};
+use crate::data::reader::MessageReader;
+use crate::data::record_class::RecordClass;
+use crate::data::record_type::RecordType;
+use crate::data::resource_record::ResourceRecord;
+use crate::data::sizes::{MAX_DNS_PACKET_SIZE, MAX_EDNS_PAYLOAD_SIZE};
+
+/// The only EDNS version defined so far
+pub(crate) const EDNS_VERSION: u8 = 0;
+
+/// Edns holds the content of the OPT pseudo-record (RFC 6891), which may be
+/// sent at most once in the additional section of a message. It reuses the
+/// fields of a [ResourceRecord] as follows:
+/// - Domain name: always the root
+/// - Type: OPT (41)
+/// - Class: UDP payload size the sender is able to receive
+/// - TTL: upper 8 bits of the response code (1 byte), version (1 byte),
+///   the DNSSEC OK flag (1 bit) and 15 unused bits. The response code is
+///   combined with the header, see [ResponseCode].
+/// - Record data: options, see [EdnsOption]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+/// EdnsOption is a single option in the data of an OPT record. Each option
+/// is encoded as its code (2 bytes), the length of its data (2 bytes) and
+/// the data itself.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdnsOption {
+    /// Extended DNS Error (RFC 8914), explaining why a request failed:
+    /// - Info code: 2 bytes, see [ExtendedErrorCode]
+    /// - Extra text: remaining bytes, utf-8 for humans
+    ExtendedError {
+        info_code: ExtendedErrorCode,
+        extra_text: String,
+    },
+    Unknown(u16, Bytes),
+}
+
+/// ExtendedErrorCode is the info code of an Extended DNS Error (RFC 8914).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtendedErrorCode {
+    Other,
+    UnsupportedDnskeyAlgorithm,
+    UnsupportedDsDigestType,
+    StaleAnswer,
+    ForgedAnswer,
+    DnssecIndeterminate,
+    DnssecBogus,
+    SignatureExpired,
+    SignatureNotYetValid,
+    DnskeyMissing,
+    RrsigsMissing,
+    NoZoneKeyBitSet,
+    NsecMissing,
+    CachedError,
+    NotReady,
+    Blocked,
+    Censored,
+    Filtered,
+    Prohibited,
+    StaleNxdomainAnswer,
+    NotAuthoritative,
+    NotSupported,
+    NoReachableAuthority,
+    NetworkError,
+    InvalidData,
+    /// Request in 0-RTT data that is unsafe to replay (RFC 9250)
+    TooEarly,
+    Unknown(u16),
+}
+
+impl Edns {
+    /// Builds the OPT record this server sends, advertising its own payload
+    /// size.
+    pub(crate) fn new(dnssec_ok: bool) -> Self {
+        Edns {
+            udp_payload_size: MAX_EDNS_PAYLOAD_SIZE as u16,
+            version: EDNS_VERSION,
+            dnssec_ok,
+            options: Vec::new(),
+        }
+    }
+
+    /// Size of the largest UDP response the sender of this record accepts.
+    /// Sizes below the limit of plain DNS are treated as that limit and it
+    /// is capped by what this server is willing to send.
+    pub(crate) fn max_response_size(&self) -> usize {
+        (self.udp_payload_size as usize).clamp(MAX_DNS_PACKET_SIZE, MAX_EDNS_PAYLOAD_SIZE)
+    }
+
+    /// Removes the OPT record from the additional section of a message and
+    /// adds its upper bits to `response_code`. More than one OPT record makes
+    /// the message invalid.
+    pub(crate) fn take_from(
+        additionals: &mut Vec<ResourceRecord>,
+        response_code: &mut ResponseCode,
+    ) -> Result<Option<Edns>, ParseError> {
+        let Some(index) = additionals
+            .iter()
+            .position(|record| record.record_type == RecordType::OPT)
+        else {
+            return Ok(None);
+        };
+        let record = additionals.remove(index);
+        if additionals[index..]
+            .iter()
+            .any(|record| record.record_type == RecordType::OPT)
+        {
+            return Err(ParseError::BadRecordData(RecordType::OPT));
+        }
+        *response_code = response_code.with_extended_bits((record.ttl >> 24) as u8);
+        Self::from_record(record).map(Some)
+    }
+
+    fn from_record(record: ResourceRecord) -> Result<Edns, ParseError> {
+        let RData::OPT(options) = record.data else {
+            return Err(ParseError::BadRecordData(RecordType::OPT));
+        };
+        if !record.domain_name.is_empty() {
+            return Err(ParseError::BadRecordData(RecordType::OPT));
+        }
+
+        Ok(Edns {
+            udp_payload_size: record.class.into(),
+            version: (record.ttl >> 16) as u8,
+            dnssec_ok: record.ttl & 0x8000 != 0,
+            options,
+        })
+    }
+
+    /// Builds the OPT record, carrying the upper bits of `response_code`.
+    pub(crate) fn to_record(&self, response_code: ResponseCode) -> ResourceRecord {
+        let mut ttl = (response_code.extended_bits() as u32) << 24 | (self.version as u32) << 16;
+        if self.dnssec_ok {
+            ttl |= 0x8000;
+        }
+        ResourceRecord {
+            domain_name: String::new(),
+            record_type: RecordType::OPT,
+            class: RecordClass::from(self.udp_payload_size),
+            ttl,
+            data: RData::OPT(self.options.clone()),
+        }
+    }
+}
+
+impl EdnsOption {
+    /// Reads options until the end of the record data.
+    pub(crate) fn read_options(reader: &mut MessageReader) -> Result<Vec<EdnsOption>, ParseError> {
+        let mut options = Vec::new();
+        while !reader.is_empty() {
+            let code = reader.read_u16()?;
+            let length = reader.read_u16()? as usize;
+            let mut data = reader.limited(length)?;
+            let option = match code {
+                15 => EdnsOption::ExtendedError {
+                    info_code: ExtendedErrorCode::from(data.read_u16()?),
+                    extra_text: String::from_utf8_lossy(data.read_slice(data.remaining())?)
+                        .into_owned(),
+                },
+                _ => EdnsOption::Unknown(code, data.read_bytes(data.remaining())?),
+            };
+            options.push(option);
+        }
+        Ok(options)
+    }
+
+    pub(crate) fn write_as_bytes(&self, output: &mut BytesMut) -> anyhow::Result<()> {
+        match self {
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                output.put_u16(15);
+                output.put_u16(u16::try_from(extra_text.len() + 2)?);
+                output.put_u16((*info_code).into());
+                output.put_slice(extra_text.as_bytes());
+            }
+            EdnsOption::Unknown(code, data) => {
+                output.put_u16(*code);
+                output.put_u16(u16::try_from(data.len())?);
+                output.put_slice(data);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for EdnsOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => write!(
+                f,
+                "EDE={} ({}) {:?}",
+                u16::from(*info_code),
+                info_code,
+                extra_text
+            ),
+            EdnsOption::Unknown(code, data) => {
+                write!(f, "OPT{}=", code)?;
+                fmt_hex(f, data)
+            }
+        }
+    }
+}
+
+impl From<u16> for ExtendedErrorCode {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Self::Other,
+            1 => Self::UnsupportedDnskeyAlgorithm,
+            2 => Self::UnsupportedDsDigestType,
+            3 => Self::StaleAnswer,
+            4 => Self::ForgedAnswer,
+            5 => Self::DnssecIndeterminate,
+            6 => Self::DnssecBogus,
+            7 => Self::SignatureExpired,
+            8 => Self::SignatureNotYetValid,
+            9 => Self::DnskeyMissing,
+            10 => Self::RrsigsMissing,
+            11 => Self::NoZoneKeyBitSet,
+            12 => Self::NsecMissing,
+            13 => Self::CachedError,
+            14 => Self::NotReady,
+            15 => Self::Blocked,
+            16 => Self::Censored,
+            17 => Self::Filtered,
+            18 => Self::Prohibited,
+            19 => Self::StaleNxdomainAnswer,
+            20 => Self::NotAuthoritative,
+            21 => Self::NotSupported,
+            22 => Self::NoReachableAuthority,
+            23 => Self::NetworkError,
+            24 => Self::InvalidData,
+            26 => Self::TooEarly,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<ExtendedErrorCode> for u16 {
+    fn from(value: ExtendedErrorCode) -> Self {
+        match value {
+            ExtendedErrorCode::Other => 0,
+            ExtendedErrorCode::UnsupportedDnskeyAlgorithm => 1,
+            ExtendedErrorCode::UnsupportedDsDigestType => 2,
+            ExtendedErrorCode::StaleAnswer => 3,
+            ExtendedErrorCode::ForgedAnswer => 4,
+            ExtendedErrorCode::DnssecIndeterminate => 5,
+            ExtendedErrorCode::DnssecBogus => 6,
+            ExtendedErrorCode::SignatureExpired => 7,
+            ExtendedErrorCode::SignatureNotYetValid => 8,
+            ExtendedErrorCode::DnskeyMissing => 9,
+            ExtendedErrorCode::RrsigsMissing => 10,
+            ExtendedErrorCode::NoZoneKeyBitSet => 11,
+            ExtendedErrorCode::NsecMissing => 12,
+            ExtendedErrorCode::CachedError => 13,
+            ExtendedErrorCode::NotReady => 14,
+            ExtendedErrorCode::Blocked => 15,
+            ExtendedErrorCode::Censored => 16,
+            ExtendedErrorCode::Filtered => 17,
+            ExtendedErrorCode::Prohibited => 18,
+            ExtendedErrorCode::StaleNxdomainAnswer => 19,
+            ExtendedErrorCode::NotAuthoritative => 20,
+            ExtendedErrorCode::NotSupported => 21,
+            ExtendedErrorCode::NoReachableAuthority => 22,
+            ExtendedErrorCode::NetworkError => 23,
+            ExtendedErrorCode::InvalidData => 24,
+            ExtendedErrorCode::TooEarly => 26,
+            ExtendedErrorCode::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for ExtendedErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ExtendedErrorCode::Other => "Other Error",
+            ExtendedErrorCode::UnsupportedDnskeyAlgorithm => "Unsupported DNSKEY Algorithm",
+            ExtendedErrorCode::UnsupportedDsDigestType => "Unsupported DS Digest Type",
+            ExtendedErrorCode::StaleAnswer => "Stale Answer",
+            ExtendedErrorCode::ForgedAnswer => "Forged Answer",
+            ExtendedErrorCode::DnssecIndeterminate => "DNSSEC Indeterminate",
+            ExtendedErrorCode::DnssecBogus => "DNSSEC Bogus",
+            ExtendedErrorCode::SignatureExpired => "Signature Expired",
+            ExtendedErrorCode::SignatureNotYetValid => "Signature Not Yet Valid",
+            ExtendedErrorCode::DnskeyMissing => "DNSKEY Missing",
+            ExtendedErrorCode::RrsigsMissing => "RRSIGs Missing",
+            ExtendedErrorCode::NoZoneKeyBitSet => "No Zone Key Bit Set",
+            ExtendedErrorCode::NsecMissing => "NSEC Missing",
+            ExtendedErrorCode::CachedError => "Cached Error",
+            ExtendedErrorCode::NotReady => "Not Ready",
+            ExtendedErrorCode::Blocked => "Blocked",
+            ExtendedErrorCode::Censored => "Censored",
+            ExtendedErrorCode::Filtered => "Filtered",
+            ExtendedErrorCode::Prohibited => "Prohibited",
+            ExtendedErrorCode::StaleNxdomainAnswer => "Stale NXDOMAIN Answer",
+            ExtendedErrorCode::NotAuthoritative => "Not Authoritative",
+            ExtendedErrorCode::NotSupported => "Not Supported",
+            ExtendedErrorCode::NoReachableAuthority => "No Reachable Authority",
+            ExtendedErrorCode::NetworkError => "Network Error",
+            ExtendedErrorCode::InvalidData => "Invalid Data",
+            ExtendedErrorCode::TooEarly => "Too Early",
+            ExtendedErrorCode::Unknown(value) => return write!(f, "Unknown Error {}", value),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opt_record(data: &'static [u8]) -> ResourceRecord {
+        let message = Bytes::from_static(data);
+        ResourceRecord::read_section(&mut MessageReader::new(&message), 1)
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn parses_opt_record() {
+        let mut additionals = vec![opt_record(&[
+            0x00, 0x00, 0x29, 0x10, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x06, 0x00, 0x0a, 0x00,
+            0x02, 0xab, 0xcd,
+        ])];
+        let mut response_code = ResponseCode::ExistingRRSet;
+        let edns = Edns::take_from(&mut additionals, &mut response_code)
+            .unwrap()
+            .unwrap();
+        assert!(additionals.is_empty());
+        assert_eq!(ResponseCode::BadCookie, response_code);
+        assert_eq!(4096, edns.udp_payload_size);
+        assert_eq!(0, edns.version);
+        assert!(edns.dnssec_ok);
+        assert_eq!(
+            vec![EdnsOption::Unknown(10, Bytes::from_static(&[0xab, 0xcd]))],
+            edns.options
+        );
+
+        let record = edns.to_record(response_code);
+        assert_eq!(RecordType::OPT, record.record_type);
+        assert_eq!(0x0100_8000, record.ttl);
+        assert_eq!(edns, Edns::from_record(record).unwrap());
+    }
+
+    #[test]
+    fn rejects_duplicate_opt_records() {
+        let data = &[
+            0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut additionals = vec![opt_record(data), opt_record(data)];
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::OPT)),
+            Edns::take_from(&mut additionals, &mut ResponseCode::NoError)
+        );
+    }
+
+    #[test]
+    fn rejects_opt_record_with_name() {
+        let mut additionals = vec![opt_record(&[
+            0x01, 0x61, 0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ])];
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::OPT)),
+            Edns::take_from(&mut additionals, &mut ResponseCode::NoError)
+        );
+    }
+
+    #[test]
+    fn limits_response_size() {
+        let mut edns = Edns::new(false);
+        assert_eq!(MAX_EDNS_PAYLOAD_SIZE, edns.max_response_size());
+        edns.udp_payload_size = 100;
+        assert_eq!(MAX_DNS_PACKET_SIZE, edns.max_response_size());
+        edns.udp_payload_size = 65535;
+        assert_eq!(MAX_EDNS_PAYLOAD_SIZE, edns.max_response_size());
+    }
+
+    #[test]
+    fn round_trips_extended_error() {
+        let message = Bytes::from_static(&[
+            0x00, 0x0f, 0x00, 0x09, 0x00, 0x17, b't', b'i', b'm', b'e', b'o', b'u', b't',
+        ]);
+        let options = EdnsOption::read_options(&mut MessageReader::new(&message)).unwrap();
+        assert_eq!(
+            vec![EdnsOption::ExtendedError {
+                info_code: ExtendedErrorCode::NetworkError,
+                extra_text: "timeout".to_string(),
+            }],
+            options
+        );
+        assert_eq!("EDE=23 (Network Error) \"timeout\"", options[0].to_string());
+
+        let mut bytes = BytesMut::new();
+        options[0].write_as_bytes(&mut bytes).unwrap();
+        assert_eq!(message, bytes);
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt::{Display, Formatter};
+
+use crate::data::record_type::RecordType;
+
+/// ParseError describes why a message could not be read from the wire.
+/// All parsing is bounds-checked, so malformed or malicious messages end up
+/// as one of these errors instead of a panic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseError {
+    /// The message ended before all announced data could be read
+    TruncatedMessage,
+    /// A label is longer than 63 bytes, i.e. its length uses one of the
+    /// reserved label types
+    LabelTooLong,
+    /// A name is longer than 255 bytes in its uncompressed form
+    NameTooLong,
+    /// A compression pointer does not point to a name before itself
+    BadPointer,
+    /// The data of a record of the given type does not match its format
+    BadRecordData(RecordType),
+    /// The questions of a response differ from the ones of its request
+    QuestionMismatch,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::TruncatedMessage => write!(f, "Message is truncated"),
+            ParseError::LabelTooLong => write!(f, "Label exceeds 63 bytes"),
+            ParseError::NameTooLong => write!(f, "Domain name exceeds 255 bytes"),
+            ParseError::BadPointer => write!(f, "Invalid domain name compression pointer"),
+            ParseError::BadRecordData(record_type) => {
+                write!(f, "Invalid data for record of type {}", record_type)
+            }
+            ParseError::QuestionMismatch => {
+                write!(f, "Response does not match the questions of the request")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -3,7 +3,7 @@ use bytes::{BufMut, BytesMut};
 
 use crate::data::sizes::REQUEST_HEADER_SIZE;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum HeaderFlagQR {
     Query,
     Reply,
@@ -18,7 +18,7 @@ impl HeaderFlagQR {
         }
     }
 
-    fn to_mask(&self) -> u16 {
+    fn to_mask(self) -> u16 {
         match self {
             HeaderFlagQR::Query => 0b0000_0000_0000_0000,
             HeaderFlagQR::Reply => 0b1000_0000_0000_0000,
@@ -26,34 +26,48 @@ impl HeaderFlagQR {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum HeaderFlagOpCode {
     Query,
     IQuery,
     Status,
+    /// Zone change notification (RFC 1996)
+    Notify,
+    /// Dynamic update (RFC 2136)
+    Update,
+    Unknown(u8),
 }
 
 impl HeaderFlagOpCode {
     fn from_flags(flags: u16) -> Self {
-        let code_field = (flags >> 11) & 0b1111;
+        let code_field = ((flags >> 11) & 0b1111) as u8;
         match code_field {
+            0 => Self::Query,
             1 => Self::IQuery,
             2 => Self::Status,
-            _ => Self::Query,
+            4 => Self::Notify,
+            5 => Self::Update,
+            _ => Self::Unknown(code_field),
         }
     }
 
-    fn to_mask(&self) -> u16 {
-        let id: u16 = match self {
+    fn to_mask(self) -> u16 {
+        let id: u8 = match self {
             HeaderFlagOpCode::Query => 0,
             HeaderFlagOpCode::IQuery => 1,
             HeaderFlagOpCode::Status => 2,
+            HeaderFlagOpCode::Notify => 4,
+            HeaderFlagOpCode::Update => 5,
+            HeaderFlagOpCode::Unknown(id) => id,
         };
-        return id << 11;
+        ((id & 0b1111) as u16) << 11
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// ResponseCode is the 12 bit result of a request. Only the lower 4 bits are
+/// part of the header, the upper 8 bits are stored in the OPT record (RFC
+/// 6891), see [crate::data::edns::Edns].
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ResponseCode {
     NoError,
     FormatError,
@@ -61,37 +75,109 @@ pub enum ResponseCode {
     NonExistentDomain,
     NotImplemented,
     Refused,
-    Unknown,
+    /// Name exists when it should not (YXDOMAIN)
+    ExistingDomain,
+    /// RRset exists when it should not (YXRRSET)
+    ExistingRRSet,
+    /// RRset that should exist does not (NXRRSET)
+    NonExistentRRSet,
+    /// Server is not authoritative for the zone or the request is not
+    /// authorized (NOTAUTH)
+    NotAuthoritative,
+    /// Name is not contained in the zone (NOTZONE)
+    NotInZone,
+    /// DSO-TYPE is not implemented (DSOTYPENI)
+    DsoTypeNotImplemented,
+    /// Bad OPT version (BADVERS), also used for bad TSIG signatures (BADSIG)
+    BadVersion,
+    BadKey,
+    BadTime,
+    BadMode,
+    BadName,
+    BadAlgorithm,
+    BadTruncation,
+    BadCookie,
+    Unknown(u16),
 }
 
 impl ResponseCode {
     fn from_flags(flags: u16) -> Self {
-        match (flags & 0b1111) as u8 {
+        Self::from(flags & 0b1111)
+    }
+
+    fn to_mask(self) -> u16 {
+        u16::from(self) & 0b1111
+    }
+
+    /// Upper 8 bits of the response code, which are stored in the OPT record.
+    pub(crate) fn extended_bits(self) -> u8 {
+        (u16::from(self) >> 4) as u8
+    }
+
+    /// Combines the lower 4 bits from the header with the upper 8 bits from
+    /// the OPT record.
+    pub(crate) fn with_extended_bits(self, extended_bits: u8) -> Self {
+        Self::from((extended_bits as u16) << 4 | self.to_mask())
+    }
+}
+
+impl From<u16> for ResponseCode {
+    fn from(value: u16) -> Self {
+        match value {
             0 => Self::NoError,
             1 => Self::FormatError,
             2 => Self::ServerFail,
             3 => Self::NonExistentDomain,
             4 => Self::NotImplemented,
             5 => Self::Refused,
-            6..=0b1111 => Self::Unknown,
-            _ => unreachable!(),
+            6 => Self::ExistingDomain,
+            7 => Self::ExistingRRSet,
+            8 => Self::NonExistentRRSet,
+            9 => Self::NotAuthoritative,
+            10 => Self::NotInZone,
+            11 => Self::DsoTypeNotImplemented,
+            16 => Self::BadVersion,
+            17 => Self::BadKey,
+            18 => Self::BadTime,
+            19 => Self::BadMode,
+            20 => Self::BadName,
+            21 => Self::BadAlgorithm,
+            22 => Self::BadTruncation,
+            23 => Self::BadCookie,
+            _ => Self::Unknown(value),
         }
     }
+}
 
-    fn to_mask(&self) -> u16 {
-        match self {
+impl From<ResponseCode> for u16 {
+    fn from(value: ResponseCode) -> Self {
+        match value {
             ResponseCode::NoError => 0,
             ResponseCode::FormatError => 1,
             ResponseCode::ServerFail => 2,
             ResponseCode::NonExistentDomain => 3,
             ResponseCode::NotImplemented => 4,
             ResponseCode::Refused => 5,
-            ResponseCode::Unknown => 15,
+            ResponseCode::ExistingDomain => 6,
+            ResponseCode::ExistingRRSet => 7,
+            ResponseCode::NonExistentRRSet => 8,
+            ResponseCode::NotAuthoritative => 9,
+            ResponseCode::NotInZone => 10,
+            ResponseCode::DsoTypeNotImplemented => 11,
+            ResponseCode::BadVersion => 16,
+            ResponseCode::BadKey => 17,
+            ResponseCode::BadTime => 18,
+            ResponseCode::BadMode => 19,
+            ResponseCode::BadName => 20,
+            ResponseCode::BadAlgorithm => 21,
+            ResponseCode::BadTruncation => 22,
+            ResponseCode::BadCookie => 23,
+            ResponseCode::Unknown(value) => value,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct DNSHeader {
     pub identification: u16,
 
@@ -101,6 +187,12 @@ pub struct DNSHeader {
     pub truncation: bool,
     pub recursion_desired: bool,
     pub recursion_available: bool,
+    /// Reserved bit, must be zero in new messages
+    pub z: bool,
+    /// All data in the response has been validated with DNSSEC (RFC 4035)
+    pub authentic_data: bool,
+    /// The client does not want the server to validate DNSSEC signatures
+    pub checking_disabled: bool,
     pub response_code: ResponseCode,
 
     pub count_questions: u16,
@@ -126,6 +218,15 @@ impl DNSHeader {
         if self.recursion_available {
             flags |= 0b0000_0000_1000_0000;
         }
+        if self.z {
+            flags |= 0b0000_0000_0100_0000;
+        }
+        if self.authentic_data {
+            flags |= 0b0000_0000_0010_0000;
+        }
+        if self.checking_disabled {
+            flags |= 0b0000_0000_0001_0000;
+        }
 
         output.put_u16(self.identification);
         output.put_u16(flags);
@@ -149,6 +250,9 @@ impl DNSHeader {
             truncation: (flags >> 9) & 1 == 1,
             recursion_desired: (flags >> 8) & 1 == 1,
             recursion_available: (flags >> 7) & 1 == 1,
+            z: (flags >> 6) & 1 == 1,
+            authentic_data: (flags >> 5) & 1 == 1,
+            checking_disabled: (flags >> 4) & 1 == 1,
             response_code: ResponseCode::from_flags(flags),
             count_questions: u16::from_be_bytes([bytes[4], bytes[5]]),
             count_answers: u16::from_be_bytes([bytes[6], bytes[7]]),
@@ -179,6 +283,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn header_from_bytes_parses_default_request_flags() {
         let bytes = &[
             0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -190,9 +295,27 @@ mod tests {
         assert_eq!(false, header.truncation);
         assert_eq!(true, header.recursion_desired);
         assert_eq!(false, header.recursion_available);
+        assert_eq!(false, header.z);
+        assert_eq!(false, header.authentic_data);
+        assert_eq!(false, header.checking_disabled);
         assert_eq!(ResponseCode::NoError, header.response_code);
     }
 
+    #[test]
+    fn header_round_trips_dnssec_flags() {
+        let bytes = &[
+            0x12, 0x34, 0x81, 0xb0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let header = DNSHeader::from_bytes(bytes).unwrap();
+        assert!(!header.z);
+        assert!(header.authentic_data);
+        assert!(header.checking_disabled);
+
+        let mut output = BytesMut::new();
+        header.write_as_bytes(&mut output);
+        assert_eq!(bytes, &output[..]);
+    }
+
     #[test]
     fn header_from_bytes_parses_count_questions() {
         let bytes = &[
@@ -228,4 +351,50 @@ mod tests {
         let header = DNSHeader::from_bytes(bytes).unwrap();
         assert_eq!(0x4444, header.count_additional);
     }
+
+    #[test]
+    fn round_trips_opcodes() {
+        for code in 0..16u16 {
+            let flags = code << 11;
+            let opcode = HeaderFlagOpCode::from_flags(flags);
+            assert_eq!(flags, opcode.to_mask());
+        }
+        assert_eq!(
+            HeaderFlagOpCode::Notify,
+            HeaderFlagOpCode::from_flags(0x2000)
+        );
+        assert_eq!(
+            HeaderFlagOpCode::Update,
+            HeaderFlagOpCode::from_flags(0x2800)
+        );
+        assert_eq!(
+            HeaderFlagOpCode::Unknown(3),
+            HeaderFlagOpCode::from_flags(0x1800)
+        );
+    }
+
+    #[test]
+    fn round_trips_response_codes() {
+        for code in 0..=0xfff {
+            assert_eq!(code, u16::from(ResponseCode::from(code)));
+        }
+        assert_eq!(ResponseCode::Unknown(12), ResponseCode::from(12));
+    }
+
+    #[test]
+    fn splits_extended_response_codes() {
+        let bytes = &[
+            0x12, 0x34, 0x81, 0x89, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let header = DNSHeader::from_bytes(bytes).unwrap();
+        assert_eq!(ResponseCode::NotAuthoritative, header.response_code);
+
+        let code = ResponseCode::BadCookie;
+        assert_eq!(1, code.extended_bits());
+        assert_eq!(7, code.to_mask());
+        assert_eq!(
+            code,
+            ResponseCode::ExistingRRSet.with_extended_bits(code.extended_bits())
+        );
+    }
 }
@@ -0,0 +1,79 @@
+use anyhow::bail;
+use bytes::{Bytes, BytesMut};
+
+use crate::data::domain_name::NameCompression;
+use crate::data::edns::Edns;
+use crate::data::header::DNSHeader;
+use crate::data::reader::MessageReader;
+use crate::data::request::DNSQuestion;
+use crate::data::resource_record::ResourceRecord;
+use crate::data::sizes::{MAX_DNS_PACKET_SIZE, REQUEST_HEADER_SIZE};
+
+/// Message holds the parsed sections of a DNS message, whose format is the
+/// same for requests and responses. The OPT record is taken out of the
+/// additional section, its upper bits of the response code are combined with
+/// the ones in the header.
+pub(crate) struct Message {
+    pub header: DNSHeader,
+    pub questions: Vec<DNSQuestion>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+    pub edns: Option<Edns>,
+}
+
+/// Reads the header and all sections of a message, the number of entries in
+/// each section is given by the counts in the header.
+pub(crate) fn read_message(message_bytes: &Bytes) -> anyhow::Result<Message> {
+    let mut reader = MessageReader::new(message_bytes);
+    let mut header = DNSHeader::from_bytes(reader.read_slice(REQUEST_HEADER_SIZE)?)?;
+    let questions = DNSQuestion::read_section(&mut reader, header.count_questions)?;
+    let answers = ResourceRecord::read_section(&mut reader, header.count_answers)?;
+    let authorities = ResourceRecord::read_section(&mut reader, header.count_authorities)?;
+    let mut additionals = ResourceRecord::read_section(&mut reader, header.count_additional)?;
+    let edns = Edns::take_from(&mut additionals, &mut header.response_code)?;
+
+    Ok(Message {
+        header,
+        questions,
+        answers,
+        authorities,
+        additionals,
+        edns,
+    })
+}
+
+/// Writes a message with the given sections. The counts in the header are
+/// taken from the number of entries in each section, the OPT record is
+/// appended to the additional section.
+pub(crate) fn write_message(
+    header: DNSHeader,
+    questions: &[DNSQuestion],
+    answers: &[ResourceRecord],
+    authorities: &[ResourceRecord],
+    additionals: &[ResourceRecord],
+    edns: Option<&Edns>,
+) -> anyhow::Result<Bytes> {
+    let response_code = header.response_code;
+    if edns.is_none() && response_code.extended_bits() != 0 {
+        bail!("Response code {:?} requires an OPT record", response_code);
+    }
+    let edns_record = edns.map(|edns| edns.to_record(response_code));
+    let header = DNSHeader {
+        count_questions: u16::try_from(questions.len())?,
+        count_answers: u16::try_from(answers.len())?,
+        count_authorities: u16::try_from(authorities.len())?,
+        count_additional: u16::try_from(additionals.len() + edns_record.iter().len())?,
+        ..header
+    };
+
+    let mut bytes = BytesMut::with_capacity(MAX_DNS_PACKET_SIZE);
+    let mut compression = NameCompression::default();
+    header.write_as_bytes(&mut bytes);
+    DNSQuestion::write_section(questions, &mut bytes, &mut compression)?;
+    ResourceRecord::write_section(answers, &mut bytes, &mut compression)?;
+    ResourceRecord::write_section(authorities, &mut bytes, &mut compression)?;
+    ResourceRecord::write_section(additionals, &mut bytes, &mut compression)?;
+    ResourceRecord::write_section(edns_record.as_slice(), &mut bytes, &mut compression)?;
+    Ok(bytes.freeze())
+}
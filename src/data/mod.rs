@@ -1,6 +1,14 @@
 mod domain_name;
+pub(crate) mod edns;
+pub(crate) mod error;
 pub(crate) mod header;
+mod message;
+pub(crate) mod rdata;
+mod reader;
+pub(crate) mod record_class;
 pub(crate) mod record_type;
 pub(crate) mod request;
+pub(crate) mod resource_record;
 pub(crate) mod response;
 pub(crate) mod sizes;
+pub(crate) mod svcb;
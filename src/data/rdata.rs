@@ -0,0 +1,652 @@
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::data::domain_name::{DomainName, NameCompression};
+use crate::data::edns::EdnsOption;
+use crate::data::error::ParseError;
+use crate::data::reader::MessageReader;
+use crate::data::record_type::RecordType;
+use crate::data::svcb::ServiceBinding;
+
+/// RData is the decoded data of a resource record. Its format depends on
+/// the type of the record:
+/// - A: IPv4 address, 4 bytes
+/// - AAAA: IPv6 address, 16 bytes
+/// - CNAME, NS: domain name of the target
+/// - MX: preference (2 bytes) followed by the domain name of the exchange
+/// - SOA: two domain names followed by five 4 byte timer values
+/// - SRV: priority, weight and port (2 bytes each) followed by the target
+/// - TXT, SPF: one or more character-strings, each prefixed by a length byte
+/// - PTR: domain name the address maps to
+/// - CAA: flags (1 byte), tag as length-prefixed string and the value
+/// - NAPTR: order and preference (2 bytes each), flags, services and regexp
+///   as character-strings followed by the domain name of the replacement
+/// - SSHFP: algorithm and fingerprint type (1 byte each) and the fingerprint
+/// - TLSA: usage, selector and matching type (1 byte each) and the data
+/// - HINFO: CPU and OS as character-strings
+/// - SVCB, HTTPS: service binding, see [ServiceBinding]
+/// - OPT: EDNS options, see [crate::data::edns::Edns]
+///
+/// Data of unknown record types is kept as is and never decompressed, as
+/// its format is unknown (RFC 3597).
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+    MX {
+        preference: u16,
+        exchange: String,
+    },
+    NS(String),
+    SOA {
+        primary_name_server: String,
+        responsible_mailbox: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum_ttl: u32,
+    },
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    TXT(Vec<Bytes>),
+    PTR(String),
+    CAA {
+        flags: u8,
+        tag: Bytes,
+        value: Bytes,
+    },
+    NAPTR {
+        order: u16,
+        preference: u16,
+        flags: Bytes,
+        services: Bytes,
+        regexp: Bytes,
+        replacement: String,
+    },
+    SSHFP {
+        algorithm: u8,
+        fingerprint_type: u8,
+        fingerprint: Bytes,
+    },
+    TLSA {
+        certificate_usage: u8,
+        selector: u8,
+        matching_type: u8,
+        data: Bytes,
+    },
+    HINFO {
+        cpu: Bytes,
+        os: Bytes,
+    },
+    SPF(Vec<Bytes>),
+    SVCB(ServiceBinding),
+    HTTPS(ServiceBinding),
+    OPT(Vec<EdnsOption>),
+    Unknown(Bytes),
+}
+
+impl RData {
+    /// Reads the record data of a record with the given type. The reader has
+    /// to be limited to the data of the record, which needs to be consumed
+    /// completely. Domain names in the data may point anywhere before them.
+    pub(crate) fn read(
+        record_type: RecordType,
+        reader: &mut MessageReader,
+    ) -> Result<RData, ParseError> {
+        let rdata = Self::read_fields(record_type, reader).map_err(|err| match err {
+            // The reader only ends early if the data is too short for its type
+            ParseError::TruncatedMessage => ParseError::BadRecordData(record_type),
+            err => err,
+        })?;
+        if !reader.is_empty() {
+            return Err(ParseError::BadRecordData(record_type));
+        }
+        Ok(rdata)
+    }
+
+    fn read_fields(
+        record_type: RecordType,
+        reader: &mut MessageReader,
+    ) -> Result<RData, ParseError> {
+        let rdata = match record_type {
+            RecordType::A => RData::A(Ipv4Addr::from(reader.read_array::<4>()?)),
+            RecordType::AAAA => RData::AAAA(Ipv6Addr::from(reader.read_array::<16>()?)),
+            RecordType::CNAME => RData::CNAME(reader.read_name()?),
+            RecordType::MX => RData::MX {
+                preference: reader.read_u16()?,
+                exchange: reader.read_name()?,
+            },
+            RecordType::NS => RData::NS(reader.read_name()?),
+            RecordType::SOA => RData::SOA {
+                primary_name_server: reader.read_name()?,
+                responsible_mailbox: reader.read_name()?,
+                serial: reader.read_u32()?,
+                refresh: reader.read_u32()?,
+                retry: reader.read_u32()?,
+                expire: reader.read_u32()?,
+                minimum_ttl: reader.read_u32()?,
+            },
+            RecordType::SRV => RData::SRV {
+                priority: reader.read_u16()?,
+                weight: reader.read_u16()?,
+                port: reader.read_u16()?,
+                target: reader.read_name()?,
+            },
+            RecordType::TXT => RData::TXT(read_character_strings(reader)?),
+            RecordType::PTR => RData::PTR(reader.read_name()?),
+            RecordType::CAA => {
+                let flags = reader.read_u8()?;
+                let tag_length = reader.read_u8()? as usize;
+                RData::CAA {
+                    flags,
+                    tag: reader.read_bytes(tag_length)?,
+                    value: reader.read_bytes(reader.remaining())?,
+                }
+            }
+            RecordType::NAPTR => RData::NAPTR {
+                order: reader.read_u16()?,
+                preference: reader.read_u16()?,
+                flags: read_character_string(reader)?,
+                services: read_character_string(reader)?,
+                regexp: read_character_string(reader)?,
+                replacement: reader.read_name()?,
+            },
+            RecordType::SSHFP => RData::SSHFP {
+                algorithm: reader.read_u8()?,
+                fingerprint_type: reader.read_u8()?,
+                fingerprint: reader.read_bytes(reader.remaining())?,
+            },
+            RecordType::TLSA => RData::TLSA {
+                certificate_usage: reader.read_u8()?,
+                selector: reader.read_u8()?,
+                matching_type: reader.read_u8()?,
+                data: reader.read_bytes(reader.remaining())?,
+            },
+            RecordType::HINFO => RData::HINFO {
+                cpu: read_character_string(reader)?,
+                os: read_character_string(reader)?,
+            },
+            RecordType::SPF => RData::SPF(read_character_strings(reader)?),
+            RecordType::SVCB => RData::SVCB(ServiceBinding::read(record_type, reader)?),
+            RecordType::HTTPS => RData::HTTPS(ServiceBinding::read(record_type, reader)?),
+            RecordType::OPT => RData::OPT(EdnsOption::read_options(reader)?),
+            RecordType::Unknown(_) => RData::Unknown(reader.read_bytes(reader.remaining())?),
+        };
+        Ok(rdata)
+    }
+
+    /// Writes the record data without its length prefix. Only names in the
+    /// data of types defined in RFC 1035 may be compressed (RFC 3597 4).
+    pub(crate) fn write_as_bytes(
+        &self,
+        output: &mut BytesMut,
+        compression: &mut NameCompression,
+    ) -> anyhow::Result<()> {
+        match self {
+            RData::A(address) => output.put_slice(&address.octets()),
+            RData::AAAA(address) => output.put_slice(&address.octets()),
+            RData::CNAME(target) | RData::NS(target) | RData::PTR(target) => {
+                DomainName::try_from_str(target)?.write_compressed(output, compression)
+            }
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                output.put_u16(*preference);
+                DomainName::try_from_str(exchange)?.write_compressed(output, compression);
+            }
+            RData::SOA {
+                primary_name_server,
+                responsible_mailbox,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum_ttl,
+            } => {
+                DomainName::try_from_str(primary_name_server)?
+                    .write_compressed(output, compression);
+                DomainName::try_from_str(responsible_mailbox)?
+                    .write_compressed(output, compression);
+                output.put_u32(*serial);
+                output.put_u32(*refresh);
+                output.put_u32(*retry);
+                output.put_u32(*expire);
+                output.put_u32(*minimum_ttl);
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                output.put_u16(*priority);
+                output.put_u16(*weight);
+                output.put_u16(*port);
+                DomainName::try_from_str(target)?.write_as_bytes(output);
+            }
+            RData::TXT(strings) | RData::SPF(strings) => {
+                for string in strings {
+                    put_character_string(output, string)?;
+                }
+            }
+            RData::CAA { flags, tag, value } => {
+                output.put_u8(*flags);
+                put_character_string(output, tag)?;
+                output.put_slice(value);
+            }
+            RData::NAPTR {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                output.put_u16(*order);
+                output.put_u16(*preference);
+                put_character_string(output, flags)?;
+                put_character_string(output, services)?;
+                put_character_string(output, regexp)?;
+                DomainName::try_from_str(replacement)?.write_as_bytes(output);
+            }
+            RData::SSHFP {
+                algorithm,
+                fingerprint_type,
+                fingerprint,
+            } => {
+                output.put_u8(*algorithm);
+                output.put_u8(*fingerprint_type);
+                output.put_slice(fingerprint);
+            }
+            RData::TLSA {
+                certificate_usage,
+                selector,
+                matching_type,
+                data,
+            } => {
+                output.put_u8(*certificate_usage);
+                output.put_u8(*selector);
+                output.put_u8(*matching_type);
+                output.put_slice(data);
+            }
+            RData::HINFO { cpu, os } => {
+                put_character_string(output, cpu)?;
+                put_character_string(output, os)?;
+            }
+            RData::SVCB(binding) | RData::HTTPS(binding) => binding.write_as_bytes(output)?,
+            RData::OPT(options) => {
+                for option in options {
+                    option.write_as_bytes(output)?;
+                }
+            }
+            RData::Unknown(data) => output.put_slice(data),
+        }
+        Ok(())
+    }
+}
+
+impl Display for RData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RData::A(address) => write!(f, "{}", address),
+            RData::AAAA(address) => write!(f, "{}", address),
+            RData::CNAME(target) | RData::NS(target) | RData::PTR(target) => {
+                write!(f, "{}.", target)
+            }
+            RData::MX {
+                preference,
+                exchange,
+            } => write!(f, "{} {}.", preference, exchange),
+            RData::SOA {
+                primary_name_server,
+                responsible_mailbox,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum_ttl,
+            } => write!(
+                f,
+                "{}. {}. {} {} {} {} {}",
+                primary_name_server,
+                responsible_mailbox,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum_ttl
+            ),
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}.", priority, weight, port, target),
+            RData::TXT(strings) | RData::SPF(strings) => {
+                for (i, string) in strings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    fmt_character_string(f, string)?;
+                }
+                Ok(())
+            }
+            RData::CAA { flags, tag, value } => {
+                write!(f, "{} {} ", flags, String::from_utf8_lossy(tag))?;
+                fmt_character_string(f, value)
+            }
+            RData::NAPTR {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                write!(f, "{} {} ", order, preference)?;
+                fmt_character_string(f, flags)?;
+                write!(f, " ")?;
+                fmt_character_string(f, services)?;
+                write!(f, " ")?;
+                fmt_character_string(f, regexp)?;
+                write!(f, " {}.", replacement)
+            }
+            RData::SSHFP {
+                algorithm,
+                fingerprint_type,
+                fingerprint,
+            } => {
+                write!(f, "{} {} ", algorithm, fingerprint_type)?;
+                fmt_hex(f, fingerprint)
+            }
+            RData::TLSA {
+                certificate_usage,
+                selector,
+                matching_type,
+                data,
+            } => {
+                write!(f, "{} {} {} ", certificate_usage, selector, matching_type)?;
+                fmt_hex(f, data)
+            }
+            RData::HINFO { cpu, os } => {
+                fmt_character_string(f, cpu)?;
+                write!(f, " ")?;
+                fmt_character_string(f, os)
+            }
+            RData::SVCB(binding) | RData::HTTPS(binding) => write!(f, "{}", binding),
+            RData::OPT(options) => {
+                for (i, option) in options.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", option)?;
+                }
+                Ok(())
+            }
+            RData::Unknown(data) => {
+                write!(f, "\\# {}", data.len())?;
+                if !data.is_empty() {
+                    write!(f, " ")?;
+                    fmt_hex(f, data)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn read_character_string(reader: &mut MessageReader) -> Result<Bytes, ParseError> {
+    let length = reader.read_u8()? as usize;
+    reader.read_bytes(length)
+}
+
+/// Reads character-strings until the end of the record data, at least one
+/// is required.
+fn read_character_strings(reader: &mut MessageReader) -> Result<Vec<Bytes>, ParseError> {
+    let mut strings = vec![read_character_string(reader)?];
+    while !reader.is_empty() {
+        strings.push(read_character_string(reader)?);
+    }
+    Ok(strings)
+}
+
+fn put_character_string(output: &mut BytesMut, string: &[u8]) -> anyhow::Result<()> {
+    if string.len() > u8::MAX as usize {
+        bail!("Character-string exceeds {} bytes", u8::MAX);
+    }
+    output.put_u8(string.len() as u8);
+    output.put_slice(string);
+    Ok(())
+}
+
+/// Writes a quoted character-string, escaping quotes, backslashes and
+/// non-printable bytes as described in RFC 1035 5.1.
+pub(crate) fn fmt_character_string(f: &mut Formatter<'_>, string: &[u8]) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for &byte in string {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+            0x20..=0x7e => write!(f, "{}", byte as char)?,
+            _ => write!(f, "\\{:03}", byte)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+pub(crate) fn fmt_hex(f: &mut Formatter<'_>, data: &[u8]) -> std::fmt::Result {
+    for byte in data {
+        write!(f, "{:02X}", byte)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(
+        record_type: u16,
+        message: &Bytes,
+        offset: usize,
+        length: usize,
+    ) -> Result<RData, ParseError> {
+        let mut reader = MessageReader::new(message);
+        reader.read_slice(offset)?;
+        RData::read(RecordType::from(record_type), &mut reader.limited(length)?)
+    }
+
+    fn round_trip(record_type: u16, data: &[u8]) -> RData {
+        let message = Bytes::copy_from_slice(data);
+        let rdata = parse(record_type, &message, 0, data.len()).unwrap();
+        let mut bytes = BytesMut::new();
+        rdata
+            .write_as_bytes(&mut bytes, &mut NameCompression::default())
+            .unwrap();
+        assert_eq!(data, &bytes[..]);
+        rdata
+    }
+
+    #[test]
+    fn round_trips_a() {
+        let rdata = round_trip(1, &[0x7f, 0x00, 0x00, 0x01]);
+        assert_eq!(RData::A(Ipv4Addr::LOCALHOST), rdata);
+        assert_eq!("127.0.0.1", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_aaaa() {
+        let mut data = [0u8; 16];
+        data[15] = 1;
+        let rdata = round_trip(28, &data);
+        assert_eq!(RData::AAAA(Ipv6Addr::LOCALHOST), rdata);
+    }
+
+    #[test]
+    fn round_trips_cname_and_ns() {
+        let data = [0x03, 0x61, 0x61, 0x61, 0x02, 0x62, 0x62, 0x00];
+        assert_eq!(RData::CNAME("aaa.bb".to_string()), round_trip(5, &data));
+        assert_eq!(RData::NS("aaa.bb".to_string()), round_trip(2, &data));
+    }
+
+    #[test]
+    fn round_trips_mx() {
+        let data = [0x00, 0x0a, 0x02, 0x6d, 0x78, 0x02, 0x62, 0x62, 0x00];
+        let rdata = round_trip(15, &data);
+        assert_eq!("10 mx.bb.", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_soa() {
+        let data = [
+            0x02, 0x6e, 0x73, 0x00, 0x02, 0x6d, 0x62, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x05,
+        ];
+        let rdata = round_trip(6, &data);
+        assert_eq!("ns. mb. 1 2 3 4 5", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_srv() {
+        let data = [
+            0x00, 0x01, 0x00, 0x02, 0x01, 0xbb, 0x03, 0x73, 0x72, 0x76, 0x00,
+        ];
+        let rdata = round_trip(33, &data);
+        assert_eq!(
+            RData::SRV {
+                priority: 1,
+                weight: 2,
+                port: 443,
+                target: "srv".to_string()
+            },
+            rdata
+        );
+    }
+
+    #[test]
+    fn round_trips_txt() {
+        let data = [0x02, 0x61, 0x22, 0x00, 0x01, 0x07];
+        let rdata = round_trip(16, &data);
+        assert_eq!("\"a\\\"\" \"\" \"\\007\"", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_ptr() {
+        let data = [0x03, 0x61, 0x61, 0x61, 0x02, 0x62, 0x62, 0x00];
+        let rdata = round_trip(12, &data);
+        assert_eq!("aaa.bb.", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_caa() {
+        let mut data = vec![0x00, 0x05];
+        data.extend_from_slice(b"issue");
+        data.extend_from_slice(b"ca.example");
+        let rdata = round_trip(257, &data);
+        assert_eq!("0 issue \"ca.example\"", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_naptr() {
+        let mut data = vec![0x00, 0x64, 0x00, 0x0a, 0x01, b'S', 0x07];
+        data.extend_from_slice(b"SIP+D2U");
+        data.extend_from_slice(&[0x00, 0x04, b'_', b's', b'i', b'p', 0x02, b'b', b'b', 0x00]);
+        let rdata = round_trip(35, &data);
+        assert_eq!("100 10 \"S\" \"SIP+D2U\" \"\" _sip.bb.", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_sshfp() {
+        let rdata = round_trip(44, &[0x04, 0x02, 0xab, 0xcd, 0xef]);
+        assert_eq!("4 2 ABCDEF", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_tlsa() {
+        let rdata = round_trip(52, &[0x03, 0x01, 0x01, 0x12, 0x34]);
+        assert_eq!("3 1 1 1234", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_hinfo() {
+        let rdata = round_trip(
+            13,
+            &[0x03, b'A', b'R', b'M', 0x05, b'L', b'i', b'n', b'u', b'x'],
+        );
+        assert_eq!("\"ARM\" \"Linux\"", rdata.to_string());
+    }
+
+    #[test]
+    fn round_trips_spf() {
+        let mut data = vec![0x06];
+        data.extend_from_slice(b"v=spf1");
+        let rdata = round_trip(99, &data);
+        assert_eq!(RData::SPF(vec![Bytes::from_static(b"v=spf1")]), rdata);
+    }
+
+    #[test]
+    fn rejects_incomplete_hinfo() {
+        let message = Bytes::from_static(&[0x03, b'A', b'R', b'M']);
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::HINFO)),
+            parse(13, &message, 0, 4)
+        );
+    }
+
+    #[test]
+    fn keeps_data_of_unknown_types() {
+        let rdata = round_trip(999, &[0x01, 0x02]);
+        assert_eq!("\\# 2 0102", rdata.to_string());
+    }
+
+    #[test]
+    fn resolves_compressed_names_against_message() {
+        let message = Bytes::from_static(&[
+            0x02, 0x62, 0x62, 0x00, 0x00, 0x0a, 0x02, 0x6d, 0x78, 0xc0, 0x00,
+        ]);
+        let rdata = parse(15, &message, 4, 7).unwrap();
+        assert_eq!("10 mx.bb.", rdata.to_string());
+    }
+
+    #[test]
+    fn rejects_data_of_invalid_length() {
+        let message = Bytes::from_static(&[0x7f, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::A)),
+            parse(1, &message, 0, 3)
+        );
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::A)),
+            parse(1, &message, 0, 5)
+        );
+        assert_eq!(Err(ParseError::TruncatedMessage), parse(1, &message, 0, 6));
+    }
+
+    #[test]
+    fn rejects_empty_txt() {
+        let message = Bytes::from_static(&[]);
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::TXT)),
+            parse(16, &message, 0, 0)
+        );
+    }
+
+    #[test]
+    fn rejects_name_exceeding_record_data() {
+        let message = Bytes::from_static(&[0x00, 0x0a, 0x02, 0x6d, 0x78, 0x00]);
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::MX)),
+            parse(15, &message, 0, 5)
+        );
+    }
+}
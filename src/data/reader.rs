@@ -0,0 +1,146 @@
+use bytes::Bytes;
+
+use crate::data::domain_name::DomainName;
+use crate::data::error::ParseError;
+
+/// MessageReader is a cursor over a complete DNS message. Every read is
+/// bounds-checked and advances the cursor. Names are resolved against the
+/// whole message, so compression pointers can be followed.
+///
+/// A reader can be limited to end before the message does, e.g. to the
+/// data of a single record, see [MessageReader::limited].
+pub(crate) struct MessageReader<'a> {
+    message: &'a Bytes,
+    position: usize,
+    end: usize,
+}
+
+impl<'a> MessageReader<'a> {
+    pub(crate) fn new(message: &'a Bytes) -> Self {
+        Self {
+            message,
+            position: 0,
+            end: message.len(),
+        }
+    }
+
+    /// Returns a reader for the next `length` bytes and advances past them.
+    pub(crate) fn limited(&mut self, length: usize) -> Result<MessageReader<'a>, ParseError> {
+        let end = self.advance(length)?;
+        Ok(Self {
+            message: self.message,
+            position: end - length,
+            end,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.position == self.end
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.end - self.position
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, ParseError> {
+        Ok(u8::from_be_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, ParseError> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, ParseError> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_slice(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn read_slice(&mut self, length: usize) -> Result<&'a [u8], ParseError> {
+        let end = self.advance(length)?;
+        Ok(&self.message[end - length..end])
+    }
+
+    /// Like [MessageReader::read_slice], but without copying the data.
+    pub(crate) fn read_bytes(&mut self, length: usize) -> Result<Bytes, ParseError> {
+        let end = self.advance(length)?;
+        Ok(self.message.slice(end - length..end))
+    }
+
+    /// Reads a domain name, following compression pointers into any part of
+    /// the message before it. The labels at the cursor have to end before
+    /// the limit of this reader.
+    pub(crate) fn read_name(&mut self) -> Result<String, ParseError> {
+        let (bytes_read, domain_name) =
+            DomainName::try_from_message(&self.message[..self.end], self.position)?;
+        self.position += bytes_read;
+        Ok(domain_name.to_string())
+    }
+
+    fn advance(&mut self, length: usize) -> Result<usize, ParseError> {
+        if self.remaining() < length {
+            return Err(ParseError::TruncatedMessage);
+        }
+        self.position += length;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_integers_in_network_order() {
+        let message = Bytes::from_static(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07]);
+        let mut reader = MessageReader::new(&message);
+        assert_eq!(Ok(0x01), reader.read_u8());
+        assert_eq!(Ok(0x0203), reader.read_u16());
+        assert_eq!(Ok(0x04050607), reader.read_u32());
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn rejects_reads_beyond_message() {
+        let message = Bytes::from_static(&[0x01, 0x02, 0x03]);
+        let mut reader = MessageReader::new(&message);
+        assert_eq!(Err(ParseError::TruncatedMessage), reader.read_u32());
+        assert_eq!(Ok(0x0102), reader.read_u16());
+        assert_eq!(Err(ParseError::TruncatedMessage), reader.read_u16());
+        assert_eq!(Err(ParseError::TruncatedMessage), reader.read_slice(2));
+        assert_eq!(1, reader.remaining());
+    }
+
+    #[test]
+    fn limits_reads_to_given_length() {
+        let message = Bytes::from_static(&[0x01, 0x02, 0x03, 0x04]);
+        let mut reader = MessageReader::new(&message);
+        let mut limited = reader.limited(2).unwrap();
+        assert_eq!(Err(ParseError::TruncatedMessage), limited.read_u32());
+        assert_eq!(Ok(0x0102), limited.read_u16());
+        assert!(limited.is_empty());
+        assert_eq!(Ok(0x0304), reader.read_u16());
+        assert!(reader.limited(1).is_err());
+    }
+
+    #[test]
+    fn reads_names_within_limit() {
+        let message = Bytes::from_static(&[
+            0x02, 0x62, 0x62, 0x00, 0x01, 0x61, 0xc0, 0x00, 0x01, 0x61, 0x00,
+        ]);
+        let mut reader = MessageReader::new(&message);
+        assert_eq!(Ok("bb".to_string()), reader.read_name());
+        assert_eq!(
+            Ok("a.bb".to_string()),
+            reader.limited(4).unwrap().read_name()
+        );
+        assert_eq!(
+            Err(ParseError::TruncatedMessage),
+            reader.limited(2).unwrap().read_name()
+        );
+    }
+}
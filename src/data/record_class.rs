@@ -0,0 +1,74 @@
+use std::fmt::{Display, Formatter};
+
+/// RecordClass is the class of a question or resource record. Nearly all
+/// data lives in the Internet class, CHAOS is commonly used to query
+/// information about the server itself.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RecordClass {
+    /// Internet
+    IN,
+    /// CHAOS
+    CH,
+    /// Hesiod
+    HS,
+    /// Used by dynamic updates to delete records (RFC 2136)
+    NONE,
+    /// Matches any class in questions
+    ANY,
+    Unknown(u16),
+}
+
+impl From<u16> for RecordClass {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Self::IN,
+            3 => Self::CH,
+            4 => Self::HS,
+            254 => Self::NONE,
+            255 => Self::ANY,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<RecordClass> for u16 {
+    fn from(value: RecordClass) -> Self {
+        match value {
+            RecordClass::IN => 1,
+            RecordClass::CH => 3,
+            RecordClass::HS => 4,
+            RecordClass::NONE => 254,
+            RecordClass::ANY => 255,
+            RecordClass::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for RecordClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordClass::Unknown(value) => write!(f, "CLASS{}", value),
+            class => write!(f, "{:?}", class),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_class_ids() {
+        for id in [1, 2, 3, 4, 254, 255, 1232] {
+            assert_eq!(id, u16::from(RecordClass::from(id)));
+        }
+    }
+
+    #[test]
+    fn displays_class_mnemonics() {
+        assert_eq!("IN", RecordClass::IN.to_string());
+        assert_eq!("CH", RecordClass::from(3).to_string());
+        assert_eq!("CLASS2", RecordClass::from(2).to_string());
+    }
+}
@@ -1,6 +1,13 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
 use anyhow::bail;
 
-#[derive(Debug, PartialEq)]
+/// RecordType is the type of a question or resource record. Types this
+/// server does not know are kept as [RecordType::Unknown], so that their
+/// records can still be passed through unchanged (RFC 3597).
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum RecordType {
     A,
     AAAA,
@@ -10,28 +17,49 @@ pub(crate) enum RecordType {
     SOA,
     SRV,
     TXT,
+    PTR,
+    CAA,
+    NAPTR,
+    SSHFP,
+    TLSA,
+    HINFO,
+    SPF,
+    SVCB,
+    HTTPS,
+    /// EDNS pseudo-record, see [crate::data::edns::Edns]
+    OPT,
+    Unknown(u16),
 }
 
-impl TryFrom<u16> for RecordType {
-    type Error = anyhow::Error;
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
         match value {
-            1 => Ok(Self::A),
-            28 => Ok(Self::AAAA),
-            5 => Ok(Self::CNAME),
-            15 => Ok(Self::MX),
-            2 => Ok(Self::NS),
-            6 => Ok(Self::SOA),
-            33 => Ok(Self::SRV),
-            16 => Ok(Self::TXT),
-            _ => bail!("Unknown record type id {}", value),
+            1 => Self::A,
+            28 => Self::AAAA,
+            5 => Self::CNAME,
+            15 => Self::MX,
+            2 => Self::NS,
+            6 => Self::SOA,
+            33 => Self::SRV,
+            16 => Self::TXT,
+            12 => Self::PTR,
+            257 => Self::CAA,
+            35 => Self::NAPTR,
+            44 => Self::SSHFP,
+            52 => Self::TLSA,
+            13 => Self::HINFO,
+            99 => Self::SPF,
+            64 => Self::SVCB,
+            65 => Self::HTTPS,
+            41 => Self::OPT,
+            _ => Self::Unknown(value),
         }
     }
 }
 
-impl Into<u16> for RecordType {
-    fn into(self) -> u16 {
-        match self {
+impl From<RecordType> for u16 {
+    fn from(value: RecordType) -> Self {
+        match value {
             RecordType::A => 1,
             RecordType::AAAA => 28,
             RecordType::CNAME => 5,
@@ -40,6 +68,92 @@ impl Into<u16> for RecordType {
             RecordType::SOA => 6,
             RecordType::SRV => 33,
             RecordType::TXT => 16,
+            RecordType::PTR => 12,
+            RecordType::CAA => 257,
+            RecordType::NAPTR => 35,
+            RecordType::SSHFP => 44,
+            RecordType::TLSA => 52,
+            RecordType::HINFO => 13,
+            RecordType::SPF => 99,
+            RecordType::SVCB => 64,
+            RecordType::HTTPS => 65,
+            RecordType::OPT => 41,
+            RecordType::Unknown(value) => value,
         }
     }
 }
+
+impl Display for RecordType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordType::Unknown(value) => write!(f, "TYPE{}", value),
+            record_type => write!(f, "{:?}", record_type),
+        }
+    }
+}
+
+/// Parses the mnemonic of a type as used in presentation format, the generic
+/// TYPEnnn form (RFC 3597 5) or a plain number.
+impl FromStr for RecordType {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let record_type = match value.to_ascii_uppercase().as_str() {
+            "A" => Self::A,
+            "AAAA" => Self::AAAA,
+            "CNAME" => Self::CNAME,
+            "MX" => Self::MX,
+            "NS" => Self::NS,
+            "SOA" => Self::SOA,
+            "SRV" => Self::SRV,
+            "TXT" => Self::TXT,
+            "PTR" => Self::PTR,
+            "CAA" => Self::CAA,
+            "NAPTR" => Self::NAPTR,
+            "SSHFP" => Self::SSHFP,
+            "TLSA" => Self::TLSA,
+            "HINFO" => Self::HINFO,
+            "SPF" => Self::SPF,
+            "SVCB" => Self::SVCB,
+            "HTTPS" => Self::HTTPS,
+            "OPT" => Self::OPT,
+            name => {
+                let id = name.strip_prefix("TYPE").unwrap_or(name);
+                match id.parse::<u16>() {
+                    Ok(id) => Self::from(id),
+                    Err(_) => bail!("Unknown record type {}", value),
+                }
+            }
+        };
+        Ok(record_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_type_ids() {
+        for id in [
+            1, 2, 5, 6, 12, 13, 15, 16, 28, 33, 35, 41, 44, 52, 64, 65, 99, 257, 65535,
+        ] {
+            assert_eq!(id, u16::from(RecordType::from(id)));
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_type_ids() {
+        assert_eq!(RecordType::Unknown(999), RecordType::from(999));
+        assert_eq!("TYPE999", RecordType::from(999).to_string());
+        assert_eq!("AAAA", RecordType::from(28).to_string());
+    }
+
+    #[test]
+    fn parses_type_names() {
+        assert_eq!(RecordType::AAAA, "aaaa".parse().unwrap());
+        assert_eq!(RecordType::HTTPS, "65".parse().unwrap());
+        assert_eq!(RecordType::Unknown(999), "TYPE999".parse().unwrap());
+        assert!("NOPE".parse::<RecordType>().is_err());
+    }
+}
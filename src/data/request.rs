@@ -1,9 +1,13 @@
-use anyhow::bail;
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 
-use crate::data::domain_name::DomainName;
+use crate::data::domain_name::{DomainName, NameCompression};
+use crate::data::edns::Edns;
 use crate::data::header::DNSHeader;
+use crate::data::message::{read_message, write_message, Message};
+use crate::data::reader::MessageReader;
+use crate::data::record_class::RecordClass;
 use crate::data::record_type::RecordType;
+use crate::data::resource_record::ResourceRecord;
 use crate::data::sizes::REQUEST_HEADER_SIZE;
 
 /// DNSQuestion represents a question to the server requesting a record
@@ -11,34 +15,48 @@ use crate::data::sizes::REQUEST_HEADER_SIZE;
 /// format:
 /// - Domain name: variable size, see below
 /// - Type of the requested record: 2 bytes, see [RecordType]
-/// - Class of the record: We'll always assume "Internet"
-#[derive(Debug)]
+/// - Class of the requested record: 2 bytes, see [RecordClass]
+#[derive(Debug, Clone)]
 pub struct DNSQuestion {
     pub record_type: RecordType,
+    pub class: RecordClass,
     pub domain_name: String,
 }
 
 impl DNSQuestion {
-    fn parse(bytes: &[u8]) -> anyhow::Result<Vec<DNSQuestion>> {
-        let mut questions = Vec::new();
-        let mut i: usize = 0;
-        while i < bytes.len() {
-            let (bytes_read, domain_name) = DomainName::try_from(&bytes[i..])?;
-            let domain_name = domain_name.to_string()?;
-            i += bytes_read; // Increment pointer past domain name
-
-            let record_type_id = u16::from_be_bytes([bytes[i], bytes[i + 1]]);
-            let record_type = RecordType::try_from(record_type_id)?;
-            questions.push(DNSQuestion {
-                record_type,
-                domain_name,
-            });
-
-            i += 2; // Increment pointer past record type
-            i += 2; // Increment pointer past ignored record class
+    /// Reads `count` consecutive questions.
+    pub(crate) fn read_section(
+        reader: &mut MessageReader,
+        count: u16,
+    ) -> anyhow::Result<Vec<DNSQuestion>> {
+        (0..count).map(|_| Self::read(reader)).collect()
+    }
+
+    /// Writes all questions of a section. See [DomainName::write_compressed]
+    /// for the requirements on `output`.
+    pub(crate) fn write_section(
+        questions: &[DNSQuestion],
+        output: &mut BytesMut,
+        compression: &mut NameCompression,
+    ) -> anyhow::Result<()> {
+        for question in questions {
+            DomainName::try_from_str(&question.domain_name)?.write_compressed(output, compression);
+            output.put_u16(question.record_type.into());
+            output.put_u16(question.class.into());
         }
+        Ok(())
+    }
+
+    fn read(reader: &mut MessageReader) -> anyhow::Result<DNSQuestion> {
+        let domain_name = reader.read_name()?;
+        let record_type = RecordType::from(reader.read_u16()?);
+        let class = RecordClass::from(reader.read_u16()?);
 
-        Ok(questions)
+        Ok(DNSQuestion {
+            record_type,
+            class,
+            domain_name,
+        })
     }
 }
 
@@ -48,34 +66,76 @@ impl DNSQuestion {
 /// It is then followed by a sequence of [DNSQuestion] to the server. The
 /// questions are directly appended to each other without any separation.
 /// I.e. the length of a single question segment can only be determined
-/// by parsing it.
+/// by parsing it. The number of questions is given by the header.
+///
+/// Requests may also carry resource records in the same sections as a
+/// response, usually an EDNS OPT record in the additional section.
 #[derive(Debug)]
 pub struct DNSRequest {
     pub header: DNSHeader,
     pub questions: Vec<DNSQuestion>,
-    raw_bytes: Option<Bytes>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+    /// Content of the OPT record, which is not part of `additionals`
+    pub edns: Option<Edns>,
 }
 
 impl DNSRequest {
-    pub(crate) fn to_bytes(&self) -> anyhow::Result<&Bytes> {
-        match &self.raw_bytes {
-            Some(bytes) => Ok(bytes),
-            None => {
-                bail!("Serialization not implemented") // todo
-            }
-        }
+    /// Serializes the request from its fields, see [write_message].
+    pub(crate) fn to_bytes(&self) -> anyhow::Result<Bytes> {
+        write_message(
+            self.header,
+            &self.questions,
+            &self.answers,
+            &self.authorities,
+            &self.additionals,
+            self.edns.as_ref(),
+        )
     }
 
     pub(crate) fn from_bytes(request_bytes: Bytes) -> anyhow::Result<Self> {
-        if request_bytes.len() < REQUEST_HEADER_SIZE {
-            bail!("Invalid request header size")
-        }
+        let Message {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+        } = read_message(&request_bytes)?;
 
-        let (header_bytes, question_bytes) = request_bytes.split_at(REQUEST_HEADER_SIZE);
         Ok(Self {
-            header: DNSHeader::from_bytes(header_bytes)?,
-            questions: DNSQuestion::parse(question_bytes)?,
-            raw_bytes: Some(request_bytes),
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+        })
+    }
+
+    /// Parses the header, questions and OPT record of a request, but skips
+    /// all other records. Used for requests that are not handled, whose
+    /// records could be invalid in a query, like the empty RDATA of RRset
+    /// deletions in updates (RFC 2136 2.5.2).
+    pub(crate) fn from_bytes_skipping_records(request_bytes: Bytes) -> anyhow::Result<Self> {
+        let mut reader = MessageReader::new(&request_bytes);
+        let mut header = DNSHeader::from_bytes(reader.read_slice(REQUEST_HEADER_SIZE)?)?;
+        let questions = DNSQuestion::read_section(&mut reader, header.count_questions)?;
+        // OPT records are only valid in the additional section
+        ResourceRecord::read_opt_records(&mut reader, header.count_answers)?;
+        ResourceRecord::read_opt_records(&mut reader, header.count_authorities)?;
+        let mut additionals =
+            ResourceRecord::read_opt_records(&mut reader, header.count_additional)?;
+        let edns = Edns::take_from(&mut additionals, &mut header.response_code)?;
+
+        Ok(Self {
+            header,
+            questions,
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns,
         })
     }
 }
@@ -84,6 +144,7 @@ impl DNSRequest {
 mod tests {
     use bytes::Bytes;
 
+    use crate::data::error::ParseError;
     use crate::data::header::HeaderFlagQR;
 
     use super::*;
@@ -107,10 +168,138 @@ mod tests {
     #[test]
     fn request_from_bytes_should_work_with_one_question() {
         let bytes = Bytes::from(vec![
-            0x12, 0x34, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
             0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x00, 0x01, 0x00, 0x01,
         ]);
         let request = DNSRequest::from_bytes(bytes).unwrap();
         assert_eq!(1, request.questions.len());
     }
+
+    #[test]
+    fn request_from_bytes_should_resolve_compressed_question_names() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+            0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x00, 0x01, 0x00, 0x01, 0x03, 0x77, 0x77, 0x77,
+            0xc0, 0x0c, 0x00, 0x1c, 0x00, 0x01,
+        ]);
+        let request = DNSRequest::from_bytes(bytes).unwrap();
+        assert_eq!(2, request.questions.len());
+        assert_eq!("zzz.AA", request.questions[0].domain_name);
+        assert_eq!("www.zzz.AA", request.questions[1].domain_name);
+        assert_eq!(RecordType::AAAA, request.questions[1].record_type);
+    }
+
+    #[test]
+    fn request_from_bytes_should_keep_unknown_question_type() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+            0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x03, 0xe7, 0x00, 0x01,
+        ]);
+        let request = DNSRequest::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(RecordType::Unknown(999), request.questions[0].record_type);
+        assert_eq!(bytes, request.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn request_from_bytes_should_keep_question_class() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x76,
+            0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x04, 0x62, 0x69, 0x6e, 0x64, 0x00, 0x00, 0x10,
+            0x00, 0x03,
+        ]);
+        let request = DNSRequest::from_bytes(bytes.clone()).unwrap();
+        assert_eq!("version.bind", request.questions[0].domain_name);
+        assert_eq!(RecordClass::CH, request.questions[0].class);
+        assert_eq!(bytes, request.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn request_from_bytes_should_parse_opt_record() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x03, 0x7a,
+            0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x29, 0x04,
+            0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        let request = DNSRequest::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(1, request.questions.len());
+        assert!(request.additionals.is_empty());
+        assert_eq!(1232, request.edns.as_ref().unwrap().udp_payload_size);
+        assert_eq!(bytes, request.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn request_from_bytes_should_ignore_data_beyond_question_count() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+        ]);
+        let request = DNSRequest::from_bytes(bytes).unwrap();
+        assert!(request.questions.is_empty());
+    }
+
+    #[test]
+    fn request_from_bytes_fails_when_questions_are_missing() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+            0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ]);
+        let err = DNSRequest::from_bytes(bytes).unwrap_err();
+        assert_eq!(
+            Some(&ParseError::TruncatedMessage),
+            err.downcast_ref::<ParseError>()
+        );
+    }
+
+    #[test]
+    fn request_from_bytes_fails_on_compression_pointer_loop() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x7a,
+            0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01,
+        ]);
+        let err = DNSRequest::from_bytes(bytes).unwrap_err();
+        assert_eq!(
+            Some(&ParseError::BadPointer),
+            err.downcast_ref::<ParseError>()
+        );
+    }
+
+    #[test]
+    fn request_to_bytes_should_compress_question_names() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+            0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x00, 0x01, 0x00, 0x01, 0x03, 0x77, 0x77, 0x77,
+            0xc0, 0x0c, 0x00, 0x1c, 0x00, 0x01,
+        ]);
+        let request = DNSRequest::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(bytes, request.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn request_to_bytes_should_keep_dots_within_labels() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x61,
+            0x2e, 0x62, 0x02, 0x63, 0x2e, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ]);
+        let request = DNSRequest::from_bytes(bytes.clone()).unwrap();
+        assert_eq!("a\\.b.c\\.", request.questions[0].domain_name);
+        assert_eq!(bytes, request.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn request_from_bytes_skipping_records_keeps_questions_and_edns() {
+        // Update deleting the A RRset of zzz.aa, which has class ANY and no
+        // RDATA, followed by an OPT record
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x28, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x03, 0x7a,
+            0x7a, 0x7a, 0x02, 0x61, 0x61, 0x00, 0x00, 0x06, 0x00, 0x01, 0xc0, 0x0c, 0x00, 0x01,
+            0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x29, 0x04, 0xd0, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        assert!(DNSRequest::from_bytes(bytes.clone()).is_err());
+
+        let request = DNSRequest::from_bytes_skipping_records(bytes).unwrap();
+        assert_eq!(1, request.questions.len());
+        assert_eq!("zzz.aa", request.questions[0].domain_name);
+        assert!(request.authorities.is_empty());
+        assert_eq!(1232, request.edns.unwrap().udp_payload_size);
+    }
 }
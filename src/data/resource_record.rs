@@ -0,0 +1,245 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::bail;
+use bytes::{BufMut, BytesMut};
+
+use crate::data::domain_name::{DomainName, NameCompression};
+use crate::data::error::ParseError;
+use crate::data::rdata::RData;
+use crate::data::reader::MessageReader;
+use crate::data::record_class::RecordClass;
+use crate::data::record_type::RecordType;
+
+/// ResourceRecord represents a single record in the answer, authority or
+/// additional section of a DNS message. It is encoded in the following format:
+/// - Domain name: variable size, may contain compression pointers
+/// - Type of the record: 2 bytes, see [RecordType]
+/// - Class of the record: 2 bytes, see [RecordClass]
+/// - TTL: 4 bytes, number of seconds the record may be cached
+/// - Length of the record data: 2 bytes
+/// - Record data: variable size, format depends on the type, see [RData]
+#[derive(Debug, Clone)]
+pub struct ResourceRecord {
+    pub domain_name: String,
+    pub record_type: RecordType,
+    pub class: RecordClass,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+impl ResourceRecord {
+    /// Reads `count` consecutive records.
+    pub(crate) fn read_section(
+        reader: &mut MessageReader,
+        count: u16,
+    ) -> Result<Vec<ResourceRecord>, ParseError> {
+        (0..count).map(|_| Self::read(reader)).collect()
+    }
+
+    /// Reads `count` consecutive records like [ResourceRecord::read_section],
+    /// but only keeps OPT records. The data of all other records is skipped
+    /// without parsing it.
+    pub(crate) fn read_opt_records(
+        reader: &mut MessageReader,
+        count: u16,
+    ) -> Result<Vec<ResourceRecord>, ParseError> {
+        let mut records = Vec::new();
+        for _ in 0..count {
+            let domain_name = reader.read_name()?;
+            let record_type = RecordType::from(reader.read_u16()?);
+            let class = RecordClass::from(reader.read_u16()?);
+            let ttl = reader.read_u32()?;
+            let data_length = reader.read_u16()? as usize;
+            let mut data_reader = reader.limited(data_length)?;
+            if record_type == RecordType::OPT {
+                records.push(ResourceRecord {
+                    domain_name,
+                    record_type,
+                    class,
+                    ttl,
+                    data: RData::read(record_type, &mut data_reader)?,
+                });
+            }
+        }
+        Ok(records)
+    }
+
+    /// Writes all records of a section. See [DomainName::write_compressed]
+    /// for the requirements on `output`.
+    pub(crate) fn write_section(
+        records: &[ResourceRecord],
+        output: &mut BytesMut,
+        compression: &mut NameCompression,
+    ) -> anyhow::Result<()> {
+        for record in records {
+            record.write_as_bytes(output, compression)?;
+        }
+        Ok(())
+    }
+
+    /// Whether both records belong to the same RRset, i.e. have the same
+    /// name, type and class.
+    pub(crate) fn is_same_rrset(&self, other: &ResourceRecord) -> bool {
+        self.record_type == other.record_type
+            && self.class == other.class
+            && self.domain_name.eq_ignore_ascii_case(&other.domain_name)
+    }
+
+    fn write_as_bytes(
+        &self,
+        output: &mut BytesMut,
+        compression: &mut NameCompression,
+    ) -> anyhow::Result<()> {
+        DomainName::try_from_str(&self.domain_name)?.write_compressed(output, compression);
+        output.put_u16(self.record_type.into());
+        output.put_u16(self.class.into());
+        output.put_u32(self.ttl);
+
+        // The length is only known once the data has been written
+        let length_offset = output.len();
+        output.put_u16(0);
+        self.data.write_as_bytes(output, compression)?;
+        let data_length = output.len() - length_offset - 2;
+        let Ok(data_length) = u16::try_from(data_length) else {
+            bail!(
+                "Data of resource record for {} is too long",
+                self.domain_name
+            );
+        };
+        output[length_offset..length_offset + 2].copy_from_slice(&data_length.to_be_bytes());
+        Ok(())
+    }
+
+    fn read(reader: &mut MessageReader) -> Result<ResourceRecord, ParseError> {
+        let domain_name = reader.read_name()?;
+        let record_type = RecordType::from(reader.read_u16()?);
+        let class = RecordClass::from(reader.read_u16()?);
+        let ttl = reader.read_u32()?;
+        let data_length = reader.read_u16()? as usize;
+        let data = RData::read(record_type, &mut reader.limited(data_length)?)?;
+
+        Ok(ResourceRecord {
+            domain_name,
+            record_type,
+            class,
+            ttl,
+            data,
+        })
+    }
+}
+
+impl Display for ResourceRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}. {} {} {} {}",
+            self.domain_name, self.ttl, self.class, self.record_type, self.data
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn parses_record_with_compressed_name() {
+        let message = Bytes::from(vec![
+            0x03, 0x7a, 0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0xc0, 0x00, 0x00, 0x01, 0x00, 0x01,
+            0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, 0x7f, 0x00, 0x00, 0x01,
+        ]);
+        let mut reader = MessageReader::new(&message);
+        reader.read_slice(8).unwrap();
+        let records = ResourceRecord::read_section(&mut reader, 1).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(1, records.len());
+        assert_eq!("zzz.AA", records[0].domain_name);
+        assert_eq!(RecordType::A, records[0].record_type);
+        assert_eq!(RecordClass::IN, records[0].class);
+        assert_eq!(3600, records[0].ttl);
+        assert_eq!(RData::A(Ipv4Addr::LOCALHOST), records[0].data);
+    }
+
+    #[test]
+    fn displays_record_in_presentation_format() {
+        let record = ResourceRecord {
+            domain_name: "zzz.aa".to_string(),
+            record_type: RecordType::A,
+            class: RecordClass::IN,
+            ttl: 60,
+            data: RData::A(Ipv4Addr::LOCALHOST),
+        };
+        assert_eq!("zzz.aa. 60 IN A 127.0.0.1", record.to_string());
+
+        let record = ResourceRecord {
+            domain_name: "zzz.aa".to_string(),
+            record_type: RecordType::Unknown(999),
+            class: RecordClass::IN,
+            ttl: 60,
+            data: RData::Unknown(Bytes::from_static(&[0x01])),
+        };
+        assert_eq!("zzz.aa. 60 IN TYPE999 \\# 1 01", record.to_string());
+    }
+
+    #[test]
+    fn writes_section_with_compressed_names() {
+        let records = [
+            ResourceRecord {
+                domain_name: "zzz.aa".to_string(),
+                record_type: RecordType::CNAME,
+                class: RecordClass::IN,
+                ttl: 60,
+                data: RData::CNAME("yy.aa".to_string()),
+            },
+            ResourceRecord {
+                domain_name: "yy.aa".to_string(),
+                record_type: RecordType::A,
+                class: RecordClass::IN,
+                ttl: 60,
+                data: RData::A(Ipv4Addr::LOCALHOST),
+            },
+        ];
+        let mut bytes = BytesMut::new();
+        ResourceRecord::write_section(&records, &mut bytes, &mut NameCompression::default())
+            .unwrap();
+        assert_eq!(
+            &[
+                0x03, 0x7a, 0x7a, 0x7a, 0x02, 0x61, 0x61, 0x00, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00,
+                0x00, 0x3c, 0x00, 0x05, 0x02, 0x79, 0x79, 0xc0, 0x04, 0xc0, 0x12, 0x00, 0x01, 0x00,
+                0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x04, 0x7f, 0x00, 0x00, 0x01
+            ],
+            &bytes[..]
+        );
+
+        let bytes = bytes.freeze();
+        let parsed = ResourceRecord::read_section(&mut MessageReader::new(&bytes), 2).unwrap();
+        assert_eq!(RData::CNAME("yy.aa".to_string()), parsed[0].data);
+        assert_eq!("yy.aa", parsed[1].domain_name);
+    }
+
+    #[test]
+    fn rejects_record_with_truncated_data() {
+        let message = Bytes::from(vec![
+            0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, 0x7f, 0x00,
+        ]);
+        assert_eq!(
+            ParseError::TruncatedMessage,
+            ResourceRecord::read_section(&mut MessageReader::new(&message), 1)
+                .err()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_section_with_missing_records() {
+        let message = Bytes::from(vec![
+            0x00, 0x00, 0x0a, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x00,
+        ]);
+        assert!(ResourceRecord::read_section(&mut MessageReader::new(&message), 1).is_ok());
+        assert!(ResourceRecord::read_section(&mut MessageReader::new(&message), 2).is_err());
+    }
+}
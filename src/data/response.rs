@@ -1,48 +1,314 @@
-use anyhow::bail;
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
 
+use crate::data::edns::Edns;
 use crate::data::header::DNSHeader;
-use crate::data::sizes::REQUEST_HEADER_SIZE;
+use crate::data::message::{read_message, write_message, Message};
+use crate::data::request::DNSQuestion;
+use crate::data::resource_record::ResourceRecord;
 
+/// A DNS response uses the same header as a request, see [DNSHeader]. It
+/// repeats the questions it answers, followed by three sections of
+/// [ResourceRecord]:
+/// - Answers: records answering the questions
+/// - Authorities: records pointing towards authoritative name servers
+/// - Additionals: records related to the answers, e.g. addresses of servers
+///
+/// The number of entries in each section is given by the counts in the header.
 #[derive(Debug)]
 pub struct DNSResponse {
     pub header: DNSHeader,
-    raw_bytes: Option<Bytes>,
+    pub questions: Vec<DNSQuestion>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+    /// Content of the OPT record, which is not part of `additionals`
+    pub edns: Option<Edns>,
 }
 
 impl DNSResponse {
-    fn serialize(self) -> anyhow::Result<Bytes> {
-        let expected_size = REQUEST_HEADER_SIZE; // todo: body?
-        let mut bytes = BytesMut::with_capacity(expected_size);
-        self.header.write_as_bytes(&mut bytes);
-        // todo: write body
-        Ok(bytes.freeze())
-    }
-
-    pub fn to_bytes(self) -> anyhow::Result<Bytes> {
-        match self.raw_bytes {
-            Some(bytes) => Ok(bytes),
-            None => self.serialize(),
+    /// Serializes the response from its fields. The counts in the header are
+    /// taken from the number of entries in each section, the OPT record is
+    /// appended to the additional section.
+    pub fn to_bytes(&self) -> anyhow::Result<Bytes> {
+        self.write_sections(
+            self.header,
+            &self.answers,
+            &self.authorities,
+            &self.additionals,
+        )
+    }
+
+    /// Serializes the response so it fits into `max_size` bytes. Additional
+    /// records are dropped first, as they are optional. If it still does not
+    /// fit, whole RRsets are removed from the end of the authority and then
+    /// the answer section and the truncation flag is set (RFC 2181 9).
+    pub(crate) fn to_bytes_limited(&self, max_size: usize) -> anyhow::Result<Bytes> {
+        let bytes = self.to_bytes()?;
+        if bytes.len() <= max_size {
+            return Ok(bytes);
+        }
+        let bytes = self.write_sections(self.header, &self.answers, &self.authorities, &[])?;
+        if bytes.len() <= max_size {
+            return Ok(bytes);
         }
+
+        let header = DNSHeader {
+            truncation: true,
+            ..self.header
+        };
+        let mut answers = &self.answers[..];
+        let mut authorities = &self.authorities[..];
+        loop {
+            if !authorities.is_empty() {
+                authorities = without_last_rrset(authorities);
+            } else {
+                answers = without_last_rrset(answers);
+            }
+            let bytes = self.write_sections(header, answers, authorities, &[])?;
+            if bytes.len() <= max_size || (answers.is_empty() && authorities.is_empty()) {
+                return Ok(bytes);
+            }
+        }
+    }
+
+    fn write_sections(
+        &self,
+        header: DNSHeader,
+        answers: &[ResourceRecord],
+        authorities: &[ResourceRecord],
+        additionals: &[ResourceRecord],
+    ) -> anyhow::Result<Bytes> {
+        write_message(
+            header,
+            &self.questions,
+            answers,
+            authorities,
+            additionals,
+            self.edns.as_ref(),
+        )
     }
 
     pub(crate) fn empty(header: DNSHeader) -> Self {
         DNSResponse {
             header,
-            raw_bytes: None,
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
         }
     }
 
     pub(crate) fn from_bytes(response_bytes: Bytes) -> anyhow::Result<Self> {
-        if response_bytes.len() < REQUEST_HEADER_SIZE {
-            bail!("Invalid request header size")
-        }
+        let Message {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+        } = read_message(&response_bytes)?;
 
-        let (header_bytes, _) = response_bytes.split_at(REQUEST_HEADER_SIZE);
-        // todo parse body
         Ok(Self {
-            header: DNSHeader::from_bytes(header_bytes)?,
-            raw_bytes: Some(response_bytes),
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
         })
     }
 }
+
+/// Removes the last RRset, i.e. all records at the end with the same name,
+/// type and class. Records of a set are expected to be next to each other.
+fn without_last_rrset(records: &[ResourceRecord]) -> &[ResourceRecord] {
+    let Some(last) = records.last() else {
+        return records;
+    };
+    let kept = records
+        .iter()
+        .rposition(|record| !record.is_same_rrset(last))
+        .map_or(0, |position| position + 1);
+    &records[..kept]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::data::header::ResponseCode;
+    use crate::data::rdata::RData;
+    use crate::data::record_class::RecordClass;
+    use crate::data::record_type::RecordType;
+
+    use super::*;
+
+    #[test]
+    fn response_from_bytes_parses_all_sections() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, // Header
+            0x03, 0x7a, 0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x00, 0x01, 0x00,
+            0x01, // Question
+            0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x04, 0x7f, 0x00,
+            0x00, 0x01, // Answer
+            0xc0, 0x10, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x05, 0x02, 0x6e,
+            0x73, 0xc0, 0x10, // Authority
+            0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Additional
+        ]);
+        let response = DNSResponse::from_bytes(bytes).unwrap();
+        assert_eq!(1, response.questions.len());
+        assert_eq!("zzz.AA", response.questions[0].domain_name);
+
+        assert_eq!(1, response.answers.len());
+        assert_eq!("zzz.AA", response.answers[0].domain_name);
+        assert_eq!(RData::A(Ipv4Addr::LOCALHOST), response.answers[0].data);
+
+        assert_eq!(1, response.authorities.len());
+        assert_eq!("AA", response.authorities[0].domain_name);
+        assert_eq!(RData::NS("ns.AA".to_string()), response.authorities[0].data);
+
+        assert!(response.additionals.is_empty());
+        assert_eq!(1232, response.edns.unwrap().udp_payload_size);
+    }
+
+    #[test]
+    fn response_to_bytes_round_trips_all_sections() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, // Header
+            0x03, 0x7a, 0x7a, 0x7a, 0x02, 0x41, 0x41, 0x00, 0x00, 0x01, 0x00,
+            0x01, // Question
+            0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x04, 0x7f, 0x00,
+            0x00, 0x01, // Answer
+            0xc0, 0x10, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x05, 0x02, 0x6e,
+            0x73, 0xc0, 0x10, // Authority
+            0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Additional
+        ]);
+        let response = DNSResponse::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(bytes, response.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn response_to_bytes_uses_section_sizes_as_counts() {
+        let mut response = DNSResponse::from_bytes(Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]))
+        .unwrap();
+        response.answers.push(ResourceRecord {
+            domain_name: "zzz.aa".to_string(),
+            record_type: RecordType::A,
+            class: RecordClass::IN,
+            ttl: 60,
+            data: RData::A(Ipv4Addr::LOCALHOST),
+        });
+
+        let bytes = response.to_bytes().unwrap();
+        let response = DNSResponse::from_bytes(bytes).unwrap();
+        assert_eq!(1, response.header.count_answers);
+        assert_eq!("zzz.aa", response.answers[0].domain_name);
+    }
+
+    #[test]
+    fn response_to_bytes_appends_opt_record() {
+        let mut response = DNSResponse::from_bytes(Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]))
+        .unwrap();
+        response.additionals.push(ResourceRecord {
+            domain_name: "zzz.aa".to_string(),
+            record_type: RecordType::A,
+            class: RecordClass::IN,
+            ttl: 60,
+            data: RData::A(Ipv4Addr::LOCALHOST),
+        });
+        response.edns = Some(Edns::new(true));
+
+        let bytes = response.to_bytes().unwrap();
+        assert_eq!(
+            &[0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00],
+            &bytes[bytes.len() - 11..]
+        );
+        let response = DNSResponse::from_bytes(bytes).unwrap();
+        assert_eq!(2, response.header.count_additional);
+        assert_eq!(1, response.additionals.len());
+        assert!(response.edns.unwrap().dnssec_ok);
+    }
+
+    #[test]
+    fn response_round_trips_extended_response_code() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // Header
+            0x00, 0x00, 0x29, 0x04, 0xd0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, // Additional
+        ]);
+        let mut response = DNSResponse::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(ResponseCode::BadCookie, response.header.response_code);
+        assert_eq!(bytes, response.to_bytes().unwrap());
+
+        response.edns = None;
+        assert!(response.to_bytes().is_err());
+    }
+
+    fn txt_record(domain_name: &str, length: usize) -> ResourceRecord {
+        ResourceRecord {
+            domain_name: domain_name.to_string(),
+            record_type: RecordType::TXT,
+            class: RecordClass::IN,
+            ttl: 60,
+            data: RData::TXT(vec![Bytes::from(vec![b'a'; length])]),
+        }
+    }
+
+    #[test]
+    fn response_to_bytes_limited_drops_additionals_first() {
+        let mut response = DNSResponse::from_bytes(Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]))
+        .unwrap();
+        response.answers.push(txt_record("zzz.aa", 100));
+        response.additionals.push(txt_record("yyy.aa", 100));
+
+        let bytes = response.to_bytes_limited(512).unwrap();
+        assert_eq!(response.to_bytes().unwrap(), bytes);
+
+        let bytes = response.to_bytes_limited(200).unwrap();
+        let limited = DNSResponse::from_bytes(bytes).unwrap();
+        assert!(!limited.header.truncation);
+        assert_eq!(1, limited.answers.len());
+        assert!(limited.additionals.is_empty());
+    }
+
+    #[test]
+    fn response_to_bytes_limited_drops_whole_rrsets() {
+        let mut response = DNSResponse::from_bytes(Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]))
+        .unwrap();
+        response.answers.push(txt_record("zzz.aa", 100));
+        response.answers.push(txt_record("yyy.aa", 100));
+        response.answers.push(txt_record("YYY.aa", 100));
+        response.authorities.push(txt_record("xxx.aa", 100));
+        response.edns = Some(Edns::new(false));
+
+        let bytes = response.to_bytes_limited(300).unwrap();
+        assert!(bytes.len() <= 300);
+        let limited = DNSResponse::from_bytes(bytes).unwrap();
+        assert!(limited.header.truncation);
+        assert_eq!(1, limited.answers.len());
+        assert_eq!("zzz.aa", limited.answers[0].domain_name);
+        assert!(limited.authorities.is_empty());
+        assert!(limited.edns.is_some());
+
+        let bytes = response.to_bytes_limited(50).unwrap();
+        let limited = DNSResponse::from_bytes(bytes).unwrap();
+        assert!(limited.header.truncation);
+        assert!(limited.answers.is_empty());
+    }
+
+    #[test]
+    fn response_from_bytes_fails_when_sections_are_missing() {
+        let bytes = Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        assert!(DNSResponse::from_bytes(bytes).is_err());
+    }
+}
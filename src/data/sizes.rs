@@ -1,2 +1,10 @@
 pub const REQUEST_HEADER_SIZE: usize = 12;
+/// Largest UDP message without EDNS (RFC 1035 4.2.1)
 pub const MAX_DNS_PACKET_SIZE: usize = 512;
+/// UDP payload size advertised via EDNS, small enough to avoid IP
+/// fragmentation on common networks
+pub const MAX_EDNS_PAYLOAD_SIZE: usize = 1232;
+/// Largest message via TCP, limited by its 2 byte length prefix
+pub const MAX_TCP_MESSAGE_SIZE: usize = 65535;
+/// Largest datagram via UDP, limited by the 2 byte length of its header
+pub const MAX_UDP_DATAGRAM_SIZE: usize = 65535;
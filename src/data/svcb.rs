@@ -0,0 +1,304 @@
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::data::domain_name::DomainName;
+use crate::data::error::ParseError;
+use crate::data::rdata::fmt_character_string;
+use crate::data::reader::MessageReader;
+use crate::data::record_type::RecordType;
+
+/// ServiceBinding is the data of SVCB and HTTPS records (RFC 9460). It is
+/// encoded in the following format:
+/// - Priority: 2 bytes, 0 marks an alias to the target
+/// - Target name: variable size, never compressed
+/// - Parameters: until the end of the record data, see [SvcParam]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceBinding {
+    pub priority: u16,
+    pub target: String,
+    pub params: Vec<SvcParam>,
+}
+
+/// SvcParam is a single parameter of a [ServiceBinding]. Each parameter is
+/// encoded as its key (2 bytes), the length of its value (2 bytes) and the
+/// value itself. Keys have to be in strictly increasing order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SvcParam {
+    /// Keys of parameters clients must understand to use the binding
+    Mandatory(Vec<u16>),
+    /// Protocol identifiers, each encoded as character-string
+    Alpn(Vec<Bytes>),
+    /// The default protocol is not supported, requires [SvcParam::Alpn]
+    NoDefaultAlpn,
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    /// Encrypted ClientHello configuration
+    Ech(Bytes),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    Unknown(u16, Bytes),
+}
+
+impl ServiceBinding {
+    pub(crate) fn read(
+        record_type: RecordType,
+        reader: &mut MessageReader,
+    ) -> Result<ServiceBinding, ParseError> {
+        let priority = reader.read_u16()?;
+        let target = reader.read_name()?;
+
+        let mut params = Vec::new();
+        let mut previous_key = None;
+        while !reader.is_empty() {
+            let key = reader.read_u16()?;
+            if previous_key.is_some_and(|previous_key| key <= previous_key) {
+                return Err(ParseError::BadRecordData(record_type));
+            }
+            previous_key = Some(key);
+
+            let length = reader.read_u16()? as usize;
+            let mut value = reader.limited(length)?;
+            params.push(SvcParam::read(key, &mut value)?);
+            if !value.is_empty() {
+                return Err(ParseError::BadRecordData(record_type));
+            }
+        }
+
+        Ok(ServiceBinding {
+            priority,
+            target,
+            params,
+        })
+    }
+
+    pub(crate) fn write_as_bytes(&self, output: &mut BytesMut) -> anyhow::Result<()> {
+        output.put_u16(self.priority);
+        DomainName::try_from_str(&self.target)?.write_as_bytes(output);
+        for param in &self.params {
+            output.put_u16(param.key());
+
+            // The length is only known once the value has been written
+            let length_offset = output.len();
+            output.put_u16(0);
+            param.write_value(output)?;
+            let length = u16::try_from(output.len() - length_offset - 2)?;
+            output[length_offset..length_offset + 2].copy_from_slice(&length.to_be_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl SvcParam {
+    fn read(key: u16, reader: &mut MessageReader) -> Result<SvcParam, ParseError> {
+        let param = match key {
+            0 => SvcParam::Mandatory(read_list(reader, |reader| reader.read_u16())?),
+            1 => SvcParam::Alpn(read_list(reader, |reader| {
+                let length = reader.read_u8()? as usize;
+                reader.read_bytes(length)
+            })?),
+            2 => SvcParam::NoDefaultAlpn,
+            3 => SvcParam::Port(reader.read_u16()?),
+            4 => SvcParam::Ipv4Hint(read_list(reader, |reader| {
+                Ok(Ipv4Addr::from(reader.read_array::<4>()?))
+            })?),
+            5 => SvcParam::Ech(reader.read_bytes(reader.remaining())?),
+            6 => SvcParam::Ipv6Hint(read_list(reader, |reader| {
+                Ok(Ipv6Addr::from(reader.read_array::<16>()?))
+            })?),
+            _ => SvcParam::Unknown(key, reader.read_bytes(reader.remaining())?),
+        };
+        Ok(param)
+    }
+
+    fn key(&self) -> u16 {
+        match self {
+            SvcParam::Mandatory(_) => 0,
+            SvcParam::Alpn(_) => 1,
+            SvcParam::NoDefaultAlpn => 2,
+            SvcParam::Port(_) => 3,
+            SvcParam::Ipv4Hint(_) => 4,
+            SvcParam::Ech(_) => 5,
+            SvcParam::Ipv6Hint(_) => 6,
+            SvcParam::Unknown(key, _) => *key,
+        }
+    }
+
+    fn write_value(&self, output: &mut BytesMut) -> anyhow::Result<()> {
+        match self {
+            SvcParam::Mandatory(keys) => keys.iter().for_each(|key| output.put_u16(*key)),
+            SvcParam::Alpn(protocols) => {
+                for protocol in protocols {
+                    output.put_u8(u8::try_from(protocol.len())?);
+                    output.put_slice(protocol);
+                }
+            }
+            SvcParam::NoDefaultAlpn => {}
+            SvcParam::Port(port) => output.put_u16(*port),
+            SvcParam::Ipv4Hint(addresses) => addresses
+                .iter()
+                .for_each(|address| output.put_slice(&address.octets())),
+            SvcParam::Ech(config) => output.put_slice(config),
+            SvcParam::Ipv6Hint(addresses) => addresses
+                .iter()
+                .for_each(|address| output.put_slice(&address.octets())),
+            SvcParam::Unknown(_, value) => output.put_slice(value),
+        }
+        Ok(())
+    }
+}
+
+/// Reads a non-empty list of values until the end of the reader.
+fn read_list<T>(
+    reader: &mut MessageReader,
+    read: impl Fn(&mut MessageReader) -> Result<T, ParseError>,
+) -> Result<Vec<T>, ParseError> {
+    let mut values = vec![read(reader)?];
+    while !reader.is_empty() {
+        values.push(read(reader)?);
+    }
+    Ok(values)
+}
+
+fn fmt_key(f: &mut Formatter<'_>, key: u16) -> std::fmt::Result {
+    match key {
+        0 => write!(f, "mandatory"),
+        1 => write!(f, "alpn"),
+        2 => write!(f, "no-default-alpn"),
+        3 => write!(f, "port"),
+        4 => write!(f, "ipv4hint"),
+        5 => write!(f, "ech"),
+        6 => write!(f, "ipv6hint"),
+        _ => write!(f, "key{}", key),
+    }
+}
+
+fn fmt_list<T: Display>(f: &mut Formatter<'_>, values: &[T]) -> std::fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", value)?;
+    }
+    Ok(())
+}
+
+impl Display for ServiceBinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}.", self.priority, self.target)?;
+        for param in &self.params {
+            write!(f, " {}", param)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SvcParam {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt_key(f, self.key())?;
+        match self {
+            SvcParam::Mandatory(keys) => {
+                write!(f, "=")?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    fmt_key(f, *key)?;
+                }
+                Ok(())
+            }
+            SvcParam::Alpn(protocols) => {
+                let protocols: Vec<_> = protocols
+                    .iter()
+                    .map(|protocol| String::from_utf8_lossy(protocol))
+                    .collect();
+                write!(f, "=\"{}\"", protocols.join(","))
+            }
+            SvcParam::NoDefaultAlpn => Ok(()),
+            SvcParam::Port(port) => write!(f, "={}", port),
+            SvcParam::Ipv4Hint(addresses) => {
+                write!(f, "=")?;
+                fmt_list(f, addresses)
+            }
+            SvcParam::Ech(config) => write!(f, "={}", BASE64_STANDARD.encode(config)),
+            SvcParam::Ipv6Hint(addresses) => {
+                write!(f, "=")?;
+                fmt_list(f, addresses)
+            }
+            SvcParam::Unknown(_, value) => {
+                write!(f, "=")?;
+                fmt_character_string(f, value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &'static [u8]) -> Result<ServiceBinding, ParseError> {
+        let message = Bytes::from_static(data);
+        ServiceBinding::read(RecordType::HTTPS, &mut MessageReader::new(&message))
+    }
+
+    #[test]
+    fn round_trips_service_binding() {
+        let data: &[u8] = &[
+            0x00, 0x01, 0x00, // Priority and target
+            0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0x00, 0x03, // Mandatory
+            0x00, 0x01, 0x00, 0x06, 0x02, b'h', b'2', 0x02, b'h', b'3', // Alpn
+            0x00, 0x02, 0x00, 0x00, // NoDefaultAlpn
+            0x00, 0x03, 0x00, 0x02, 0x01, 0xbb, // Port
+            0x00, 0x04, 0x00, 0x04, 0x7f, 0x00, 0x00, 0x01, // Ipv4Hint
+            0x00, 0x05, 0x00, 0x03, 0x01, 0x02, 0x03, // Ech
+            0x00, 0x06, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // Ipv6Hint
+            0x01, 0x00, 0x00, 0x01, b'x', // Unknown
+        ];
+        let binding = parse(data).unwrap();
+        assert_eq!(8, binding.params.len());
+        assert_eq!(SvcParam::Port(443), binding.params[3]);
+
+        let mut bytes = BytesMut::new();
+        binding.write_as_bytes(&mut bytes).unwrap();
+        assert_eq!(data, &bytes[..]);
+        assert_eq!(
+            "1 . mandatory=alpn,port alpn=\"h2,h3\" no-default-alpn port=443 ipv4hint=127.0.0.1 \
+             ech=AQID ipv6hint=::1 key256=\"x\"",
+            binding.to_string()
+        );
+    }
+
+    #[test]
+    fn parses_alias_mode() {
+        let binding = parse(&[0x00, 0x00, 0x02, b'b', b'b', 0x00]).unwrap();
+        assert_eq!(0, binding.priority);
+        assert_eq!("bb", binding.target);
+        assert!(binding.params.is_empty());
+    }
+
+    #[test]
+    fn rejects_unordered_keys() {
+        let data = &[
+            0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x02, 0x01, 0xbb, 0x00, 0x02, 0x00, 0x00,
+        ];
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::HTTPS)),
+            parse(data)
+        );
+    }
+
+    #[test]
+    fn rejects_values_of_invalid_length() {
+        let data = &[0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x03, 0x01, 0xbb, 0x00];
+        assert_eq!(
+            Err(ParseError::BadRecordData(RecordType::HTTPS)),
+            parse(data)
+        );
+
+        let data = &[0x00, 0x01, 0x00, 0x00, 0x04, 0x00, 0x00];
+        assert_eq!(Err(ParseError::TruncatedMessage), parse(data));
+    }
+}
@@ -0,0 +1,401 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Body;
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use log::debug;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::config::ServerConfig;
+use crate::data::edns::Edns;
+use crate::data::header::*;
+use crate::data::rdata::RData;
+use crate::data::record_class::RecordClass;
+use crate::data::record_type::RecordType;
+use crate::data::request::{DNSQuestion, DNSRequest};
+use crate::data::resource_record::ResourceRecord;
+use crate::data::response::DNSResponse;
+use crate::data::sizes::MAX_TCP_MESSAGE_SIZE;
+use crate::handler::{parse_and_handle_request, Transport};
+
+/// Path of DNS over HTTPS, also serving the JSON API
+const DNS_QUERY_PATH: &str = "/dns-query";
+/// Path of the JSON API as used by Google
+const RESOLVE_PATH: &str = "/resolve";
+const DNS_MESSAGE: &str = "application/dns-message";
+const DNS_JSON: &str = "application/dns-json";
+
+/// Serves DNS over HTTPS (RFC 8484) via HTTP/1.1 and HTTP/2 on an
+/// established TLS connection. HTTP/1.1 clients have to send their headers
+/// within the idle timeout, HTTP/2 connections are closed once their client
+/// stops answering pings.
+pub(crate) async fn serve_connection<S>(stream: S, config: Arc<ServerConfig>) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(config.idle_timeout);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(config.idle_timeout)
+        .keep_alive_timeout(config.idle_timeout);
+
+    let service = service_fn(move |request| {
+        let config = config.clone();
+        async move { Ok::<_, Infallible>(handle(request, &config).await) }
+    });
+    builder
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+        .map_err(|err| anyhow!(err))
+}
+
+/// Answers a single HTTP request. DNS messages are accepted base64url
+/// encoded in the `dns` parameter of GET requests or as body of POST
+/// requests. GET requests with a `name` parameter instead use the JSON API.
+async fn handle<B>(request: Request<B>, config: &ServerConfig) -> Response<Full<Bytes>>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let path = request.uri().path();
+    let result = match *request.method() {
+        Method::GET if path == RESOLVE_PATH => answer_json(&request, config).await,
+        Method::GET if path == DNS_QUERY_PATH => match query_param(&request, "dns") {
+            Some(message) => match BASE64_URL_SAFE_NO_PAD.decode(message) {
+                Ok(request_bytes) => answer_message(Bytes::from(request_bytes), config).await,
+                Err(_) => Err(StatusCode::BAD_REQUEST),
+            },
+            None => answer_json(&request, config).await,
+        },
+        Method::POST if path == DNS_QUERY_PATH => {
+            let is_dns_message = request
+                .headers()
+                .get(CONTENT_TYPE)
+                .is_some_and(|content_type| content_type == DNS_MESSAGE);
+            if is_dns_message {
+                match Limited::new(request.into_body(), MAX_TCP_MESSAGE_SIZE)
+                    .collect()
+                    .await
+                {
+                    Ok(body) => answer_message(body.to_bytes(), config).await,
+                    Err(_) => Err(StatusCode::PAYLOAD_TOO_LARGE),
+                }
+            } else {
+                Err(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            }
+        }
+        _ if path == DNS_QUERY_PATH || path == RESOLVE_PATH => Err(StatusCode::METHOD_NOT_ALLOWED),
+        _ => Err(StatusCode::NOT_FOUND),
+    };
+
+    result.unwrap_or_else(|status| {
+        let mut response = Response::new(Full::default());
+        *response.status_mut() = status;
+        response
+    })
+}
+
+async fn answer_message(
+    request_bytes: Bytes,
+    config: &ServerConfig,
+) -> Result<Response<Full<Bytes>>, StatusCode> {
+    let response_bytes = handle_message(request_bytes, config).await?;
+    let response = DNSResponse::from_bytes(response_bytes.clone())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Response::builder()
+        .header(CONTENT_TYPE, DNS_MESSAGE)
+        .header(CACHE_CONTROL, format!("max-age={}", max_age(&response)))
+        .body(Full::new(response_bytes))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Answers a question given by the `name` and `type` parameters in the JSON
+/// format of the Google and Cloudflare APIs. The `cd` and `do` parameters set
+/// the flags of the same name.
+async fn answer_json<B>(
+    request: &Request<B>,
+    config: &ServerConfig,
+) -> Result<Response<Full<Bytes>>, StatusCode> {
+    let domain_name = query_param(request, "name").ok_or(StatusCode::BAD_REQUEST)?;
+    let record_type = match query_param(request, "type") {
+        Some(record_type) => record_type.parse().map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => RecordType::A,
+    };
+    let flag = |key| {
+        query_param(request, key)
+            .is_some_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+    };
+
+    let dns_request = DNSRequest {
+        header: DNSHeader {
+            identification: 0,
+            msg_type: HeaderFlagQR::Query,
+            opcode: HeaderFlagOpCode::Query,
+            authoritative: false,
+            truncation: false,
+            recursion_desired: true,
+            recursion_available: false,
+            z: false,
+            authentic_data: false,
+            checking_disabled: flag("cd"),
+            response_code: ResponseCode::NoError,
+            count_questions: 0,
+            count_answers: 0,
+            count_authorities: 0,
+            count_additional: 0,
+        },
+        questions: vec![DNSQuestion {
+            record_type,
+            class: RecordClass::IN,
+            domain_name: domain_name.trim_end_matches('.').to_string(),
+        }],
+        answers: Vec::new(),
+        authorities: Vec::new(),
+        additionals: Vec::new(),
+        edns: Some(Edns::new(flag("do"))),
+    };
+    // Fails for invalid names
+    let request_bytes = dns_request
+        .to_bytes()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let response_bytes = handle_message(request_bytes, config).await?;
+    let response =
+        DNSResponse::from_bytes(response_bytes).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let header = &response.header;
+    let mut body = json!({
+        "Status": u16::from(header.response_code),
+        "TC": header.truncation,
+        "RD": header.recursion_desired,
+        "RA": header.recursion_available,
+        "AD": header.authentic_data,
+        "CD": header.checking_disabled,
+        "Question": response.questions.iter().map(|question| json!({
+            "name": format!("{}.", question.domain_name),
+            "type": u16::from(question.record_type),
+        })).collect::<Vec<_>>(),
+    });
+    if !response.answers.is_empty() {
+        body["Answer"] = records_json(&response.answers);
+    }
+    if !response.authorities.is_empty() {
+        body["Authority"] = records_json(&response.authorities);
+    }
+
+    Response::builder()
+        .header(CONTENT_TYPE, DNS_JSON)
+        .header(CACHE_CONTROL, format!("max-age={}", max_age(&response)))
+        .body(Full::new(Bytes::from(body.to_string())))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn handle_message(request_bytes: Bytes, config: &ServerConfig) -> Result<Bytes, StatusCode> {
+    match parse_and_handle_request(request_bytes, Transport::Https, config).await {
+        Ok(Some(response_bytes)) => Ok(response_bytes),
+        Ok(None) => Err(StatusCode::BAD_REQUEST),
+        Err(err) => {
+            debug!("Rejecting DNS over HTTPS request: {:?}", err);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+fn records_json(records: &[ResourceRecord]) -> Value {
+    records
+        .iter()
+        .map(|record| {
+            json!({
+                "name": format!("{}.", record.domain_name),
+                "type": u16::from(record.record_type),
+                "TTL": record.ttl,
+                "data": record.data.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Responses may be cached by HTTP as long as by DNS (RFC 8484 5.1), i.e.
+/// until the first answer expires. Negative answers may be cached as long as
+/// the SOA record in their authority section allows, which is the smaller of
+/// its TTL and its MINIMUM field (RFC 2308 5).
+fn max_age(response: &DNSResponse) -> u32 {
+    if !response.answers.is_empty() {
+        return response
+            .answers
+            .iter()
+            .map(|record| record.ttl)
+            .min()
+            .unwrap_or(0);
+    }
+    response
+        .authorities
+        .iter()
+        .filter_map(|record| match record.data {
+            RData::SOA { minimum_ttl, .. } => Some(record.ttl.min(minimum_ttl)),
+            _ => None,
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+fn query_param<B>(request: &Request<B>, key: &str) -> Option<String> {
+    let query = request.uri().query()?;
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::handler::tests::chaos_request_bytes;
+
+    use super::*;
+
+    fn get(uri: &str) -> Request<Full<Bytes>> {
+        Request::get(uri).body(Full::default()).unwrap()
+    }
+
+    async fn body(response: Response<Full<Bytes>>) -> Bytes {
+        response.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn answers_dns_messages_via_get_and_post() {
+        let config = ServerConfig::default();
+        let uri = format!(
+            "/dns-query?dns={}",
+            BASE64_URL_SAFE_NO_PAD.encode(chaos_request_bytes(0, "version.bind"))
+        );
+        let response = handle(get(&uri), &config).await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(DNS_MESSAGE, response.headers()[CONTENT_TYPE]);
+        assert_eq!("max-age=0", response.headers()[CACHE_CONTROL]);
+        let message = DNSResponse::from_bytes(body(response).await).unwrap();
+        assert_eq!(1, message.answers.len());
+
+        let request = Request::post(DNS_QUERY_PATH)
+            .header(CONTENT_TYPE, DNS_MESSAGE)
+            .body(Full::new(chaos_request_bytes(0, "version.bind")))
+            .unwrap();
+        let response = handle(request, &config).await;
+        assert_eq!(StatusCode::OK, response.status());
+        let message = DNSResponse::from_bytes(body(response).await).unwrap();
+        assert_eq!(1, message.answers.len());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_http_requests() {
+        let config = ServerConfig::default();
+        let request = Request::post(DNS_QUERY_PATH)
+            .header(CONTENT_TYPE, "text/plain")
+            .body(Full::new(chaos_request_bytes(0, "version.bind")))
+            .unwrap();
+        assert_eq!(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            handle(request, &config).await.status()
+        );
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            handle(get("/dns-query?dns=!!"), &config).await.status()
+        );
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            handle(get("/dns-query"), &config).await.status()
+        );
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            handle(get("/index.html"), &config).await.status()
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_json_api_with_cache_lifetime_of_answers() {
+        let record = |ttl| ResourceRecord {
+            domain_name: "zzz.aa".to_string(),
+            record_type: RecordType::A,
+            class: RecordClass::IN,
+            ttl,
+            data: RData::A(Ipv4Addr::LOCALHOST),
+        };
+        let config = ServerConfig {
+            records: vec![record(300), record(60)],
+            ..ServerConfig::default()
+        };
+
+        let response = handle(get("/resolve?name=zzz.aa.&type=a"), &config).await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(DNS_JSON, response.headers()[CONTENT_TYPE]);
+        assert_eq!("max-age=60", response.headers()[CACHE_CONTROL]);
+        let json: Value = serde_json::from_slice(&body(response).await).unwrap();
+        assert_eq!(0, json["Status"]);
+        assert_eq!("zzz.aa.", json["Question"][0]["name"]);
+        assert_eq!(1, json["Answer"][0]["type"]);
+        assert_eq!(300, json["Answer"][0]["TTL"]);
+        assert_eq!("127.0.0.1", json["Answer"][0]["data"]);
+        assert!(json.get("Authority").is_none());
+
+        let response = handle(get("/dns-query?name=zzz.aa&type=NOPE"), &config).await;
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn caches_negative_answers_up_to_soa_minimum() {
+        let mut response = DNSResponse::from_bytes(chaos_request_bytes(0, "version.bind")).unwrap();
+        assert_eq!(0, max_age(&response));
+
+        let soa = |ttl, minimum_ttl| ResourceRecord {
+            domain_name: "aa".to_string(),
+            record_type: RecordType::SOA,
+            class: RecordClass::IN,
+            ttl,
+            data: RData::SOA {
+                primary_name_server: "ns.aa".to_string(),
+                responsible_mailbox: "admin.aa".to_string(),
+                serial: 1,
+                refresh: 7200,
+                retry: 3600,
+                expire: 86400,
+                minimum_ttl,
+            },
+        };
+        response.authorities.push(soa(3600, 300));
+        assert_eq!(300, max_age(&response));
+        response.authorities[0] = soa(60, 300);
+        assert_eq!(60, max_age(&response));
+    }
+
+    #[tokio::test]
+    async fn serves_http1_connections() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        tokio::spawn(serve_connection(server, Arc::new(ServerConfig::default())));
+
+        let request = format!(
+            "GET /dns-query?dns={} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            BASE64_URL_SAFE_NO_PAD.encode(chaos_request_bytes(0, "version.bind"))
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("content-type: application/dns-message\r\n"));
+    }
+}
@@ -1,45 +1,616 @@
+use anyhow::bail;
 use bytes::Bytes;
 use log::{debug, error, trace};
+use tokio::time::error::Elapsed;
 
+use crate::config::ServerConfig;
+use crate::data::edns::{Edns, EdnsOption, ExtendedErrorCode, EDNS_VERSION};
+use crate::data::error::ParseError;
 use crate::data::header::*;
-use crate::data::request::DNSRequest;
+use crate::data::rdata::RData;
+use crate::data::record_class::RecordClass;
+use crate::data::record_type::RecordType;
+use crate::data::request::{DNSQuestion, DNSRequest};
+use crate::data::resource_record::ResourceRecord;
 use crate::data::response::DNSResponse;
+use crate::data::sizes::{MAX_DNS_PACKET_SIZE, MAX_TCP_MESSAGE_SIZE, REQUEST_HEADER_SIZE};
 use crate::resolver::resolve_upstream;
 
-async fn handle_request(request: &DNSRequest) -> anyhow::Result<DNSResponse> {
-    // todo: check overrides
+async fn handle_request(
+    request: &DNSRequest,
+    config: &ServerConfig,
+) -> anyhow::Result<DNSResponse> {
+    if let Some(edns) = &request.edns {
+        if edns.version > EDNS_VERSION {
+            debug!("Not handling request with EDNS version {}", edns.version);
+            return Ok(reply(request, ResponseCode::BadVersion));
+        }
+    }
+
+    if let Some(question) = request.questions.first() {
+        match question.class {
+            RecordClass::IN => {}
+            RecordClass::CH => return Ok(answer_chaos(request, question, config)),
+            class => {
+                debug!("Not handling question of unsupported class {}", class);
+                return Ok(reply(request, ResponseCode::NotImplemented));
+            }
+        }
+    }
+
+    if let Some(response) = answer_local(request, config) {
+        return Ok(response);
+    }
+
     // todo: check cache
     let upstream_response = resolve_upstream(request).await?;
     trace!("Got upstream response {:?}", upstream_response);
+    for record in upstream_response
+        .answers
+        .iter()
+        .chain(&upstream_response.authorities)
+        .chain(&upstream_response.additionals)
+    {
+        debug!("Upstream record {}", record);
+    }
 
     // todo: store in cache
-    return Ok(upstream_response);
+    Ok(upstream_response)
+}
+
+/// Answers CHAOS class queries for information about the server itself.
+/// Only the names commonly used for this are supported, their values are
+/// taken from the config.
+fn answer_chaos(
+    request: &DNSRequest,
+    question: &DNSQuestion,
+    config: &ServerConfig,
+) -> DNSResponse {
+    let value = if question.domain_name.eq_ignore_ascii_case("version.bind") {
+        &config.version
+    } else if question.domain_name.eq_ignore_ascii_case("hostname.bind") {
+        &config.hostname
+    } else {
+        &None
+    };
+    let Some(value) = value else {
+        return reply(request, ResponseCode::Refused);
+    };
+
+    let mut response = reply(request, ResponseCode::NoError);
+    response.header.authoritative = true;
+    if question.record_type == RecordType::TXT {
+        response.answers.push(ResourceRecord {
+            domain_name: question.domain_name.clone(),
+            record_type: RecordType::TXT,
+            class: RecordClass::CH,
+            ttl: 0,
+            data: RData::TXT(vec![Bytes::copy_from_slice(value.as_bytes())]),
+        });
+    }
+    response
+}
+
+/// Answers the first question from the records in the config, if any of them
+/// matches its name, type and class.
+fn answer_local(request: &DNSRequest, config: &ServerConfig) -> Option<DNSResponse> {
+    let question = request.questions.first()?;
+    let answers: Vec<ResourceRecord> = config
+        .records
+        .iter()
+        .filter(|record| {
+            record.record_type == question.record_type
+                && record.class == question.class
+                && record
+                    .domain_name
+                    .trim_end_matches('.')
+                    .eq_ignore_ascii_case(question.domain_name.trim_end_matches('.'))
+        })
+        .cloned()
+        .collect();
+    if answers.is_empty() {
+        return None;
+    }
+
+    let mut response = reply(request, ResponseCode::NoError);
+    response.header.authoritative = true;
+    response.answers = answers;
+    Some(response)
+}
+
+/// Explains why handling a request failed with an Extended DNS Error, so the
+/// reason is visible to clients.
+fn extended_error(err: &anyhow::Error) -> EdnsOption {
+    let (info_code, extra_text) = if err.downcast_ref::<Elapsed>().is_some() {
+        (ExtendedErrorCode::NetworkError, "Upstream timed out")
+    } else if err.downcast_ref::<std::io::Error>().is_some() {
+        (ExtendedErrorCode::NetworkError, "Upstream unreachable")
+    } else if err.downcast_ref::<ParseError>().is_some() {
+        (ExtendedErrorCode::InvalidData, "Invalid upstream response")
+    } else {
+        (ExtendedErrorCode::Other, "Internal error")
+    };
+    EdnsOption::ExtendedError {
+        info_code,
+        extra_text: extra_text.to_string(),
+    }
+}
+
+/// Builds a reply to `request` without any records, echoing its questions.
+fn reply(request: &DNSRequest, response_code: ResponseCode) -> DNSResponse {
+    let mut response = DNSResponse::empty(reply_header(&request.header, response_code));
+    response.questions = request.questions.clone();
+    response
 }
 
-pub async fn parse_and_handle_request(request_bytes: Bytes) -> anyhow::Result<DNSResponse> {
-    let request = DNSRequest::from_bytes(request_bytes)?;
+/// Builds a FORMERR reply to a request that could not be parsed, which only
+/// mirrors its header.
+fn format_error(request_header: &DNSHeader, err: &anyhow::Error) -> DNSResponse {
+    debug!(
+        "Replying FORMERR to malformed request {}: {}",
+        request_header.identification, err
+    );
+    DNSResponse::empty(reply_header(request_header, ResponseCode::FormatError))
+}
+
+/// Builds the header of a reply synthesized by this server, mirroring the
+/// ID, opcode and flags of the request.
+fn reply_header(request_header: &DNSHeader, response_code: ResponseCode) -> DNSHeader {
+    DNSHeader {
+        identification: request_header.identification,
+        msg_type: HeaderFlagQR::Reply,
+        opcode: request_header.opcode,
+        authoritative: false,
+        truncation: false,
+        recursion_desired: request_header.recursion_desired,
+        recursion_available: true,
+        z: false,
+        // Local answers are not validated
+        authentic_data: false,
+        checking_disabled: request_header.checking_disabled,
+        response_code,
+        count_questions: 0,
+        count_answers: 0,
+        count_authorities: 0,
+        count_additional: 0,
+    }
+}
+
+/// Transport a request was received on, which limits the size of responses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Udp,
+    /// Any stream transport where messages are prefixed with their length
+    Tcp,
+    /// DNS over HTTPS, with messages as bodies of HTTP requests and responses
+    Https,
+    /// DNS over QUIC, with requests in 0-RTT data marked as early
+    Quic {
+        early_data: bool,
+    },
+}
+
+/// Handles a request and serializes the response. Via UDP the response is
+/// limited to the payload size the client advertised via EDNS, or 512 bytes
+/// without it. Larger responses are truncated to whole RRsets with the
+/// truncation flag set, so the client can retry via TCP. Malformed
+/// requests are answered with FORMERR if their header is readable, responses
+/// are ignored and yield no reply.
+pub async fn parse_and_handle_request(
+    request_bytes: Bytes,
+    transport: Transport,
+    config: &ServerConfig,
+) -> anyhow::Result<Option<Bytes>> {
+    let Some(header_bytes) = request_bytes.get(..REQUEST_HEADER_SIZE) else {
+        bail!(
+            "Request of {}b is too short for a header",
+            request_bytes.len()
+        );
+    };
+    let header = DNSHeader::from_bytes(header_bytes)?;
+    if header.msg_type == HeaderFlagQR::Reply {
+        // Answering responses could start a loop with another server
+        debug!("Ignoring response {}", header.identification);
+        return Ok(None);
+    }
+    // Only queries are implemented, for other opcodes just the questions are
+    // parsed to echo them. 0-RTT data can be replayed by an attacker, so
+    // these are refused in it, explained by an extended error if the client
+    // sent EDNS (RFC 9250 4.5).
+    if header.opcode != HeaderFlagOpCode::Query {
+        let request = match DNSRequest::from_bytes_skipping_records(request_bytes) {
+            Ok(request) => request,
+            Err(err) => return Ok(Some(format_error(&header, &err).to_bytes()?)),
+        };
+        let response_code = if transport == (Transport::Quic { early_data: true }) {
+            ResponseCode::Refused
+        } else {
+            ResponseCode::NotImplemented
+        };
+        debug!(
+            "Replying {:?} to request {} with opcode {:?}",
+            response_code, header.identification, header.opcode
+        );
+        let mut response = reply(&request, response_code);
+        if response_code == ResponseCode::Refused {
+            response.edns = request.edns.as_ref().map(|edns| Edns {
+                options: vec![EdnsOption::ExtendedError {
+                    info_code: ExtendedErrorCode::TooEarly,
+                    extra_text: String::new(),
+                }],
+                ..Edns::new(edns.dnssec_ok)
+            });
+        }
+        return Ok(Some(response.to_bytes()?));
+    }
+
+    let request = match DNSRequest::from_bytes(request_bytes) {
+        Ok(request) => request,
+        Err(err) => return Ok(Some(format_error(&header, &err).to_bytes()?)),
+    };
     debug!(
         "Handling request {} with {} questions",
         request.header.identification, request.header.count_questions
     );
+    for question in &request.questions {
+        debug!(
+            "Question for {} {} record of {}",
+            question.record_type, question.class, question.domain_name
+        );
+    }
     trace!("Handling request {:?}", request);
 
-    let response = handle_request(&request).await.unwrap_or_else(|err| {
-        error!("Error while handling request: {:?}", err);
-        DNSResponse::empty(DNSHeader {
-            identification: request.header.identification,
-            msg_type: HeaderFlagQR::Reply,
-            opcode: request.header.opcode,
-            authoritative: request.header.authoritative,
-            truncation: false,
-            recursion_desired: false,
-            recursion_available: false,
-            response_code: ResponseCode::ServerFail,
-            count_questions: 0,
-            count_answers: 0,
-            count_authorities: 0,
-            count_additional: 0,
-        })
+    let mut response = handle_request(&request, config)
+        .await
+        .unwrap_or_else(|err| {
+            error!("Error while handling request: {:?}", err);
+            let mut response = reply(&request, ResponseCode::ServerFail);
+            response.edns = Some(Edns {
+                options: vec![extended_error(&err)],
+                ..Edns::new(false)
+            });
+            response
+        });
+
+    // Clients only understand an OPT record in the response if they sent one.
+    // Extended errors are passed on, other options only concern the sender.
+    let extended_errors: Vec<EdnsOption> = response
+        .edns
+        .take()
+        .into_iter()
+        .flat_map(|edns| edns.options)
+        .filter(|option| matches!(option, EdnsOption::ExtendedError { .. }))
+        .collect();
+    response.edns = request.edns.as_ref().map(|edns| Edns {
+        options: extended_errors,
+        ..Edns::new(edns.dnssec_ok)
     });
-    Ok(response)
+    if response.edns.is_none() && response.header.response_code.extended_bits() != 0 {
+        debug!(
+            "Replacing response code {:?} the client cannot receive",
+            response.header.response_code
+        );
+        response.header.response_code = ResponseCode::ServerFail;
+    }
+
+    let max_size = match transport {
+        Transport::Udp => request
+            .edns
+            .as_ref()
+            .map_or(MAX_DNS_PACKET_SIZE, Edns::max_response_size),
+        Transport::Tcp | Transport::Https | Transport::Quic { .. } => MAX_TCP_MESSAGE_SIZE,
+    };
+    let response_bytes = response.to_bytes_limited(max_size)?;
+    Ok(Some(response_bytes))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::data::svcb::{ServiceBinding, SvcParam};
+
+    use super::*;
+
+    /// Builds a query with a single question and the RD bit set, shared by
+    /// the tests of all transports.
+    pub(crate) fn request_bytes(
+        identification: u16,
+        domain_name: &str,
+        record_type: u16,
+        class: u16,
+    ) -> Bytes {
+        let mut bytes = identification.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        for label in domain_name.split('.') {
+            bytes.push(label.len() as u8);
+            bytes.extend_from_slice(label.as_bytes());
+        }
+        bytes.push(0x00);
+        bytes.extend_from_slice(&record_type.to_be_bytes());
+        bytes.extend_from_slice(&class.to_be_bytes());
+        Bytes::from(bytes)
+    }
+
+    /// Builds a CHAOS TXT query like the ones for `version.bind`
+    pub(crate) fn chaos_request_bytes(identification: u16, domain_name: &str) -> Bytes {
+        request_bytes(identification, domain_name, 16, 3)
+    }
+
+    fn chaos_request(domain_name: &str) -> DNSRequest {
+        request_for(domain_name, 16, 3)
+    }
+
+    fn request_for(domain_name: &str, record_type: u16, class: u16) -> DNSRequest {
+        DNSRequest::from_bytes(request_bytes(0x1234, domain_name, record_type, class)).unwrap()
+    }
+
+    #[test]
+    fn answers_chaos_version_from_config() {
+        let config = ServerConfig {
+            version: Some("test".to_string()),
+            ..ServerConfig::default()
+        };
+        let request = chaos_request("VERSION.bind");
+        let response = answer_chaos(&request, &request.questions[0], &config);
+        assert_eq!(ResponseCode::NoError, response.header.response_code);
+        assert_eq!(1, response.questions.len());
+        assert_eq!(1, response.answers.len());
+        assert_eq!(
+            "VERSION.bind. 0 CH TXT \"test\"",
+            response.answers[0].to_string()
+        );
+    }
+
+    #[test]
+    fn refuses_unconfigured_chaos_names() {
+        let config = ServerConfig {
+            hostname: None,
+            ..ServerConfig::default()
+        };
+        for domain_name in ["hostname.bind", "example.com"] {
+            let request = chaos_request(domain_name);
+            let response = answer_chaos(&request, &request.questions[0], &config);
+            assert_eq!(ResponseCode::Refused, response.header.response_code);
+            assert!(response.answers.is_empty());
+        }
+    }
+
+    #[test]
+    fn answers_matching_records_from_config() {
+        let config = ServerConfig {
+            records: vec![ResourceRecord {
+                domain_name: "zzz.aa".to_string(),
+                record_type: RecordType::HTTPS,
+                class: RecordClass::IN,
+                ttl: 300,
+                data: RData::HTTPS(ServiceBinding {
+                    priority: 1,
+                    target: String::new(),
+                    params: vec![
+                        SvcParam::Alpn(vec![Bytes::from_static(b"h2")]),
+                        SvcParam::Port(443),
+                    ],
+                }),
+            }],
+            ..ServerConfig::default()
+        };
+
+        let request = request_for("ZZZ.aa", 65, 1);
+        let response = answer_local(&request, &config).unwrap();
+        assert_eq!(ResponseCode::NoError, response.header.response_code);
+        assert!(response.header.authoritative);
+        assert_eq!(
+            "zzz.aa. 300 IN HTTPS 1 . alpn=\"h2\" port=443",
+            response.answers[0].to_string()
+        );
+
+        assert!(answer_local(&request_for("zzz.aa", 64, 1), &config).is_none());
+        assert!(answer_local(&request_for("yyy.aa", 65, 1), &config).is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_opcodes() {
+        let config = ServerConfig::default();
+        for opcode in [
+            HeaderFlagOpCode::IQuery,
+            HeaderFlagOpCode::Status,
+            HeaderFlagOpCode::Notify,
+            HeaderFlagOpCode::Update,
+            HeaderFlagOpCode::Unknown(9),
+        ] {
+            for (transport, response_code) in [
+                (Transport::Udp, ResponseCode::NotImplemented),
+                (Transport::Tcp, ResponseCode::NotImplemented),
+                (Transport::Quic { early_data: true }, ResponseCode::Refused),
+            ] {
+                let mut request = request_for("zzz.aa", 6, 1);
+                request.header.opcode = opcode;
+                let response_bytes =
+                    parse_and_handle_request(request.to_bytes().unwrap(), transport, &config)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                let response = DNSResponse::from_bytes(response_bytes).unwrap();
+                assert_eq!(response_code, response.header.response_code);
+                assert_eq!(opcode, response.header.opcode);
+                assert_eq!("zzz.aa", response.questions[0].domain_name);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_unsafe_opcodes_in_early_data() {
+        let config = ServerConfig::default();
+        let mut request = chaos_request("version.bind");
+        request.edns = Some(Edns::new(false));
+        let early_data = Transport::Quic { early_data: true };
+
+        let response_bytes =
+            parse_and_handle_request(request.to_bytes().unwrap(), early_data, &config)
+                .await
+                .unwrap()
+                .unwrap();
+        let response = DNSResponse::from_bytes(response_bytes).unwrap();
+        assert_eq!(ResponseCode::NoError, response.header.response_code);
+
+        request.header.opcode = HeaderFlagOpCode::Notify;
+        let response_bytes =
+            parse_and_handle_request(request.to_bytes().unwrap(), early_data, &config)
+                .await
+                .unwrap()
+                .unwrap();
+        let response = DNSResponse::from_bytes(response_bytes).unwrap();
+        assert_eq!(ResponseCode::Refused, response.header.response_code);
+        assert_eq!(HeaderFlagOpCode::Notify, response.header.opcode);
+        assert_eq!(
+            vec![EdnsOption::ExtendedError {
+                info_code: ExtendedErrorCode::TooEarly,
+                extra_text: String::new(),
+            }],
+            response.edns.unwrap().options
+        );
+    }
+
+    #[tokio::test]
+    async fn replies_not_implemented_to_updates_without_parsing_them() {
+        // Deletes the A RRset of zzz.aa, which has class ANY and no RDATA
+        let request_bytes = Bytes::from_static(&[
+            0x12, 0x34, 0x28, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x03, 0x7a,
+            0x7a, 0x7a, 0x02, 0x61, 0x61, 0x00, 0x00, 0x06, 0x00, 0x01, 0xc0, 0x0c, 0x00, 0x01,
+            0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        let response_bytes =
+            parse_and_handle_request(request_bytes, Transport::Udp, &ServerConfig::default())
+                .await
+                .unwrap()
+                .unwrap();
+        let response = DNSResponse::from_bytes(response_bytes).unwrap();
+        assert_eq!(0x1234, response.header.identification);
+        assert_eq!(HeaderFlagOpCode::Update, response.header.opcode);
+        assert_eq!(ResponseCode::NotImplemented, response.header.response_code);
+        assert_eq!(1, response.questions.len());
+        assert_eq!("zzz.aa", response.questions[0].domain_name);
+        assert_eq!(RecordType::SOA, response.questions[0].record_type);
+        assert!(response.authorities.is_empty());
+    }
+
+    #[tokio::test]
+    async fn replies_format_error_to_malformed_requests() {
+        let request_bytes = Bytes::from(vec![
+            0x12, 0x34, 0x01, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+        ]);
+        let response_bytes =
+            parse_and_handle_request(request_bytes, Transport::Udp, &ServerConfig::default())
+                .await
+                .unwrap()
+                .unwrap();
+        let response = DNSResponse::from_bytes(response_bytes).unwrap();
+        assert_eq!(0x1234, response.header.identification);
+        assert_eq!(HeaderFlagQR::Reply, response.header.msg_type);
+        assert_eq!(ResponseCode::FormatError, response.header.response_code);
+        assert!(response.header.recursion_desired);
+        assert!(response.header.recursion_available);
+        assert!(response.header.checking_disabled);
+        assert!(response.questions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignores_responses_and_short_packets() {
+        let config = ServerConfig::default();
+        let response_bytes = Bytes::from(vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a,
+        ]);
+        assert!(
+            parse_and_handle_request(response_bytes, Transport::Udp, &config)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let short_bytes = Bytes::from(vec![0x12, 0x34, 0x01]);
+        assert!(
+            parse_and_handle_request(short_bytes, Transport::Udp, &config)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn explains_errors_with_extended_errors() {
+        let err = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+        assert!(matches!(
+            extended_error(&err),
+            EdnsOption::ExtendedError {
+                info_code: ExtendedErrorCode::NetworkError,
+                ..
+            }
+        ));
+
+        let err = anyhow::Error::from(ParseError::TruncatedMessage).context("Upstream");
+        assert!(matches!(
+            extended_error(&err),
+            EdnsOption::ExtendedError {
+                info_code: ExtendedErrorCode::InvalidData,
+                ..
+            }
+        ));
+
+        let err = anyhow::Error::from(ParseError::QuestionMismatch);
+        assert!(matches!(
+            extended_error(&err),
+            EdnsOption::ExtendedError {
+                info_code: ExtendedErrorCode::InvalidData,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_edns_version() {
+        let mut request = request_for("zzz.aa", 1, 1);
+        request.edns = Some(Edns {
+            version: 1,
+            ..Edns::new(false)
+        });
+        let response = handle_request(&request, &ServerConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(ResponseCode::BadVersion, response.header.response_code);
+    }
+
+    #[tokio::test]
+    async fn limits_response_size_to_edns_payload_size() {
+        let record = ResourceRecord {
+            domain_name: "zzz.aa".to_string(),
+            record_type: RecordType::TXT,
+            class: RecordClass::IN,
+            ttl: 60,
+            data: RData::TXT(vec![Bytes::from(vec![b'a'; 100])]),
+        };
+        let config = ServerConfig {
+            records: vec![record; 8],
+            ..ServerConfig::default()
+        };
+
+        let mut request = request_for("zzz.aa", 16, 1);
+        let response_bytes =
+            parse_and_handle_request(request.to_bytes().unwrap(), Transport::Udp, &config)
+                .await
+                .unwrap()
+                .unwrap();
+        let response = DNSResponse::from_bytes(response_bytes).unwrap();
+        assert!(response.header.truncation);
+        assert!(response.answers.is_empty());
+        assert!(response.edns.is_none());
+
+        request.edns = Some(Edns::new(true));
+        let response_bytes =
+            parse_and_handle_request(request.to_bytes().unwrap(), Transport::Udp, &config)
+                .await
+                .unwrap()
+                .unwrap();
+        let response = DNSResponse::from_bytes(response_bytes).unwrap();
+        assert!(!response.header.truncation);
+        assert_eq!(8, response.answers.len());
+        assert!(response.edns.unwrap().dnssec_ok);
+    }
 }
@@ -1,17 +1,29 @@
 use log::LevelFilter;
 
-use crate::config::ServerConfig;
+use crate::config::{ServerConfig, TlsConfig};
 use crate::server::DNSServer;
 
 mod config;
 mod data;
+mod doh;
 mod handler;
+mod quic;
 mod resolver;
 mod server;
+mod tls;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = ServerConfig::default();
+    let mut config = ServerConfig::default();
+    if let (Some(certificate_path), Some(key_path)) = (
+        std::env::var_os("DNS_TLS_CERTIFICATE"),
+        std::env::var_os("DNS_TLS_KEY"),
+    ) {
+        config = config.with_tls(TlsConfig {
+            certificate_path: certificate_path.into(),
+            key_path: key_path.into(),
+        });
+    }
     pretty_env_logger::formatted_timed_builder()
         .filter_level(LevelFilter::Trace)
         .init();
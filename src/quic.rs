@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use bytes::{Buf, Bytes};
+use log::{debug, info, warn};
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::{
+    Connection, ConnectionError, Endpoint, EndpointConfig, Incoming, ReadError, ReadToEndError,
+    RecvStream, SendStream, TokioRuntime, TransportConfig, VarInt,
+};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+use crate::config::{ServerConfig, TlsConfig};
+use crate::data::sizes::{MAX_TCP_MESSAGE_SIZE, REQUEST_HEADER_SIZE};
+use crate::handler::{parse_and_handle_request, Transport};
+use crate::tls;
+
+/// ALPN protocol of DNS over QUIC
+const DOQ_ALPN: &[u8] = b"doq";
+
+/// No error, used when closing idle connections
+const DOQ_NO_ERROR: VarInt = VarInt::from_u32(0x0);
+/// The server is incapable of pursuing the transaction
+const DOQ_INTERNAL_ERROR: VarInt = VarInt::from_u32(0x1);
+/// The client violated the protocol, e.g. with a malformed request stream
+const DOQ_PROTOCOL_ERROR: VarInt = VarInt::from_u32(0x2);
+/// The client cancelled the transaction
+const DOQ_REQUEST_CANCELLED: VarInt = VarInt::from_u32(0x3);
+/// The server is closing the connection due to excessive load
+const DOQ_EXCESSIVE_LOAD: VarInt = VarInt::from_u32(0x4);
+
+/// Creates a QUIC endpoint serving DNS over QUIC (RFC 9250) on `socket`.
+/// Clients resuming a session may send requests as 0-RTT data. DoQ uses no
+/// unidirectional streams, so clients may not open any.
+pub(crate) fn endpoint(socket: std::net::UdpSocket, tls: &TlsConfig) -> anyhow::Result<Endpoint> {
+    let crypto = tls::server_config(tls, &[DOQ_ALPN], true)?;
+    let mut transport = TransportConfig::default();
+    transport.max_concurrent_uni_streams(VarInt::from_u32(0));
+    let mut server_config =
+        quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    server_config.transport_config(Arc::new(transport));
+
+    Ok(Endpoint::new(
+        EndpointConfig::default(),
+        Some(server_config),
+        socket,
+        Arc::new(TokioRuntime),
+    )?)
+}
+
+/// Accepts QUIC connections up to the configured limit. Connections beyond
+/// it are closed right after the handshake with DOQ_EXCESSIVE_LOAD.
+pub(crate) async fn serve(endpoint: Endpoint, config: Arc<ServerConfig>) -> anyhow::Result<()> {
+    let connections = Arc::new(Semaphore::new(config.max_connections));
+    while let Some(incoming) = endpoint.accept().await {
+        let addr = incoming.remote_address();
+        let permit = connections.clone().try_acquire_owned().ok();
+        debug!("Accepted QUIC connection from {}", addr);
+        let config = config.clone();
+        tokio::spawn(async move {
+            let result = match permit {
+                Some(_) => handle_connection(incoming, config).await,
+                None => reject_connection(incoming).await,
+            };
+            if let Err(err) = result {
+                warn!("Closed QUIC connection from {}: {:?}", addr, err);
+            }
+        });
+    }
+    bail!("QUIC endpoint closed")
+}
+
+async fn reject_connection(incoming: Incoming) -> anyhow::Result<()> {
+    let connection = incoming.accept()?.await?;
+    warn!(
+        "Rejecting QUIC connection from {}, too many connections",
+        connection.remote_address()
+    );
+    connection.close(DOQ_EXCESSIVE_LOAD, b"Too many connections");
+    Ok(())
+}
+
+/// Handles the requests of a connection, each on its own bidirectional
+/// stream. Streams are accepted before the handshake completes, so requests
+/// in 0-RTT data are answered right away. The connection is closed with
+/// DOQ_NO_ERROR once the client is idle for too long and all pending
+/// responses are sent.
+async fn handle_connection(incoming: Incoming, config: Arc<ServerConfig>) -> anyhow::Result<()> {
+    let connecting = incoming.accept()?;
+    // Always succeeds for incoming connections
+    let connection = match connecting.into_0rtt() {
+        Ok((connection, _)) => connection,
+        Err(connecting) => connecting.await?,
+    };
+
+    let mut requests = JoinSet::new();
+    loop {
+        while requests.try_join_next().is_some() {}
+        let (send, recv) = match timeout(config.idle_timeout, connection.accept_bi()).await {
+            Ok(Ok(streams)) => streams,
+            Ok(Err(
+                ConnectionError::ApplicationClosed(_)
+                | ConnectionError::LocallyClosed
+                | ConnectionError::TimedOut,
+            )) => {
+                return Ok(());
+            }
+            Ok(Err(err)) => return Err(err).context("Failed to accept QUIC stream"),
+            Err(_) => {
+                debug!(
+                    "Closing idle QUIC connection from {}",
+                    connection.remote_address()
+                );
+                while requests.join_next().await.is_some() {}
+                connection.close(DOQ_NO_ERROR, b"");
+                return Ok(());
+            }
+        };
+        requests.spawn(handle_stream(
+            send,
+            recv,
+            connection.clone(),
+            config.clone(),
+        ));
+    }
+}
+
+/// Answers the single request on a stream (RFC 9250 4.2). Malformed
+/// requests close the whole connection with DOQ_PROTOCOL_ERROR, while
+/// cancelled requests and internal errors only reset the stream.
+async fn handle_stream(
+    mut send: SendStream,
+    mut recv: RecvStream,
+    connection: Connection,
+    config: Arc<ServerConfig>,
+) {
+    let early_data = recv.is_0rtt();
+    let message = match recv.read_to_end(2 + MAX_TCP_MESSAGE_SIZE).await {
+        Ok(message) => message,
+        Err(ReadToEndError::Read(ReadError::Reset(_))) => {
+            debug!("Request cancelled by {}", connection.remote_address());
+            let _ = send.reset(DOQ_REQUEST_CANCELLED);
+            return;
+        }
+        Err(ReadToEndError::Read(ReadError::ConnectionLost(_))) => return,
+        Err(err) => return protocol_error(&connection, &err.to_string()),
+    };
+    let request_bytes = match read_request(Bytes::from(message)) {
+        Ok(request_bytes) => request_bytes,
+        Err(err) => return protocol_error(&connection, &err.to_string()),
+    };
+
+    match parse_and_handle_request(request_bytes, Transport::Quic { early_data }, &config).await {
+        Ok(Some(response_bytes)) => {
+            if let Err(err) = write_response(&mut send, &response_bytes).await {
+                debug!(
+                    "Failed to send response to {}: {:?}",
+                    connection.remote_address(),
+                    err
+                );
+            }
+        }
+        Ok(None) => protocol_error(&connection, "Received a response instead of a request"),
+        Err(err) => {
+            warn!(
+                "Dropped request from {}: {:?}",
+                connection.remote_address(),
+                err
+            );
+            let _ = send.reset(DOQ_INTERNAL_ERROR);
+        }
+    }
+}
+
+/// Reads a request from the whole data of a stream, prefixed with its length
+/// like via TCP. The message ID has to be 0, as streams already tell the
+/// requests apart (RFC 9250 4.2.1).
+fn read_request(mut message: Bytes) -> anyhow::Result<Bytes> {
+    if message.len() < 2 {
+        bail!("Request stream of {}b lacks a length", message.len());
+    }
+    let len = message.get_u16() as usize;
+    if len != message.len() {
+        bail!(
+            "Request length of {}b does not match the {}b stream",
+            len,
+            message.len()
+        );
+    }
+    if len < REQUEST_HEADER_SIZE {
+        bail!("Request of {}b is too short for a header", len);
+    }
+    if message[..2] != [0, 0] {
+        bail!("Request has a non-zero message ID");
+    }
+    Ok(message)
+}
+
+async fn write_response(send: &mut SendStream, response_bytes: &[u8]) -> anyhow::Result<()> {
+    send.write_all(&u16::try_from(response_bytes.len())?.to_be_bytes())
+        .await?;
+    send.write_all(response_bytes).await?;
+    send.finish()?;
+    // Closing the connection before the response is acknowledged would drop it
+    send.stopped().await?;
+    Ok(())
+}
+
+fn protocol_error(connection: &Connection, reason: &str) {
+    info!(
+        "Closing QUIC connection from {} after protocol error: {}",
+        connection.remote_address(),
+        reason
+    );
+    connection.close(DOQ_PROTOCOL_ERROR, reason.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use quinn::crypto::rustls::QuicClientConfig;
+    use tokio_rustls::rustls::client::ClientConfig;
+    use tokio_rustls::rustls::crypto::ring::default_provider;
+    use tokio_rustls::rustls::version::TLS13;
+    use tokio_rustls::rustls::RootCertStore;
+
+    use crate::handler::tests::chaos_request_bytes;
+    use crate::server::tests::test_tls_config;
+
+    use super::*;
+
+    /// Starts a server and returns a client trusting its certificate
+    fn start_server(name: &str) -> (Endpoint, SocketAddr) {
+        let (tls, certificate) = test_tls_config(name);
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = endpoint(socket, &tls).unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(serve(server, Arc::new(ServerConfig::default())));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate).unwrap();
+        let mut crypto = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_protocol_versions(&[&TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        crypto.alpn_protocols = vec![DOQ_ALPN.to_vec()];
+        crypto.enable_early_data = true;
+        let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+            QuicClientConfig::try_from(crypto).unwrap(),
+        )));
+        (client, addr)
+    }
+
+    async fn query(connection: &Connection, request: &[u8]) -> Result<Vec<u8>, ReadToEndError> {
+        let (mut send, mut recv) = connection.open_bi().await.unwrap();
+        send.write_all(&(request.len() as u16).to_be_bytes())
+            .await
+            .unwrap();
+        send.write_all(request).await.unwrap();
+        send.finish().unwrap();
+        recv.read_to_end(MAX_TCP_MESSAGE_SIZE).await
+    }
+
+    #[tokio::test]
+    async fn answers_requests_on_separate_streams() {
+        let version_request = chaos_request_bytes(0, "version.bind");
+        let (client, addr) = start_server("doq");
+        let connection = client.connect(addr, "localhost").unwrap().await.unwrap();
+
+        let (first, second) = tokio::join!(
+            query(&connection, &version_request),
+            query(&connection, &version_request)
+        );
+        for response in [first.unwrap(), second.unwrap()] {
+            let len = u16::from_be_bytes([response[0], response[1]]) as usize;
+            assert_eq!(response.len() - 2, len);
+            // Message ID 0 and one answer
+            assert_eq!([0x00, 0x00], response[2..4]);
+            assert_eq!([0x00, 0x01], response[8..10]);
+        }
+    }
+
+    #[tokio::test]
+    async fn closes_connection_on_non_zero_message_id() {
+        let version_request = chaos_request_bytes(0, "version.bind");
+        let (client, addr) = start_server("doq-id");
+        let connection = client.connect(addr, "localhost").unwrap().await.unwrap();
+
+        let mut request = version_request.to_vec();
+        request[1] = 0x01;
+        let err = query(&connection, &request).await.unwrap_err();
+        let ReadToEndError::Read(ReadError::ConnectionLost(ConnectionError::ApplicationClosed(
+            close,
+        ))) = err
+        else {
+            panic!("Unexpected error {:?}", err);
+        };
+        assert_eq!(DOQ_PROTOCOL_ERROR, close.error_code);
+    }
+
+    #[tokio::test]
+    async fn refuses_unsafe_opcodes_in_early_data() {
+        let version_request = chaos_request_bytes(0, "version.bind");
+        let (client, addr) = start_server("doq-0rtt");
+        // Receive a session ticket from a first connection
+        let connection = client.connect(addr, "localhost").unwrap().await.unwrap();
+        query(&connection, &version_request).await.unwrap();
+        connection.close(DOQ_NO_ERROR, b"");
+
+        let Ok((connection, accepted)) = client.connect(addr, "localhost").unwrap().into_0rtt()
+        else {
+            panic!("Session was not resumed");
+        };
+        let mut notify = version_request.to_vec();
+        notify[2] = 0x21;
+        let (query_response, notify_response) = tokio::join!(
+            query(&connection, &version_request),
+            query(&connection, &notify)
+        );
+        assert!(accepted.await);
+        // NOERROR for the query, REFUSED for the notify
+        assert_eq!(0x00, query_response.unwrap()[5] & 0x0f);
+        assert_eq!(0x05, notify_response.unwrap()[5] & 0x0f);
+    }
+}
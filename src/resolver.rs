@@ -1,30 +1,203 @@
-use bytes::BytesMut;
-use log::info;
-use tokio::net::UdpSocket;
-use tokio::time::Instant;
+use std::time::Duration;
 
+use bytes::{Bytes, BytesMut};
+use log::{debug, info};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::{timeout, Instant};
+
+use crate::data::edns::Edns;
+use crate::data::error::ParseError;
+use crate::data::header::{DNSHeader, ResponseCode};
 use crate::data::request::DNSRequest;
 use crate::data::response::DNSResponse;
-use crate::data::sizes::MAX_DNS_PACKET_SIZE;
+use crate::data::sizes::MAX_EDNS_PAYLOAD_SIZE;
 
-const UPSTREAM: &'static str = "1.1.1.1:53"; // todo get from config
+const UPSTREAM: &str = "1.1.1.1:53"; // todo get from config
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Forwards the questions of `request` to the upstream server. The upstream
+/// request advertises the payload size of this server via EDNS, independent
+/// of what the client supports. The AD and CD bits of the client are passed
+/// on, so upstream validates and reports the result like for the client.
 pub(crate) async fn resolve_upstream(request: &DNSRequest) -> anyhow::Result<DNSResponse> {
-    let request_bytes = request.to_bytes()?;
+    resolve(request, UPSTREAM).await
+}
 
-    let start_time = Instant::now();
-    let sock = UdpSocket::bind("0.0.0.0:0").await?;
-    sock.send_to(request_bytes, UPSTREAM).await?;
+/// Sends the request via UDP first. Truncated answers are requested again
+/// from the same upstream via TCP to get the complete response.
+async fn resolve(request: &DNSRequest, upstream: &str) -> anyhow::Result<DNSResponse> {
+    let upstream_request = DNSRequest {
+        header: DNSHeader {
+            z: false,
+            ..request.header
+        },
+        questions: request.questions.clone(),
+        answers: Vec::new(),
+        authorities: Vec::new(),
+        additionals: Vec::new(),
+        edns: Some(Edns::new(
+            request.edns.as_ref().is_some_and(|edns| edns.dnssec_ok),
+        )),
+    };
+    let request_bytes = upstream_request.to_bytes()?;
 
-    let mut response_buffer = BytesMut::with_capacity(MAX_DNS_PACKET_SIZE);
-    let (len, _) = sock.recv_buf_from(&mut response_buffer).await?;
+    let start_time = Instant::now();
+    let response_bytes = timeout(UPSTREAM_TIMEOUT, query_udp(&request_bytes, upstream)).await??;
+    let mut response = DNSResponse::from_bytes(response_bytes)?;
+    if response.header.truncation {
+        debug!("Upstream response is truncated, retrying via TCP");
+        let response_bytes =
+            timeout(UPSTREAM_TIMEOUT, query_tcp(&request_bytes, upstream)).await??;
+        response = DNSResponse::from_bytes(response_bytes)?;
+    }
     let request_duration = start_time.elapsed();
     info!(
-        "Received upstream {}b response in {}ms",
-        len,
+        "Received upstream response in {}ms",
         request_duration.as_millis()
     );
 
-    let response_buffer = response_buffer.freeze();
-    DNSResponse::from_bytes(response_buffer)
+    // Errors like FORMERR or REFUSED may be sent without the questions
+    let echoes_questions = !response.questions.is_empty()
+        || matches!(
+            response.header.response_code,
+            ResponseCode::NoError | ResponseCode::NonExistentDomain
+        );
+    let answers_request = response.questions.len() == request.questions.len()
+        && response
+            .questions
+            .iter()
+            .zip(&request.questions)
+            .all(|(answered, asked)| {
+                answered.record_type == asked.record_type
+                    && answered.class == asked.class
+                    && answered
+                        .domain_name
+                        .eq_ignore_ascii_case(&asked.domain_name)
+            });
+    if echoes_questions && !answers_request {
+        return Err(ParseError::QuestionMismatch.into());
+    }
+
+    Ok(response)
+}
+
+async fn query_udp(request_bytes: &[u8], upstream: &str) -> anyhow::Result<Bytes> {
+    let sock = UdpSocket::bind("0.0.0.0:0").await?;
+    sock.send_to(request_bytes, upstream).await?;
+
+    let mut response_buffer = BytesMut::with_capacity(MAX_EDNS_PAYLOAD_SIZE);
+    let (len, _) = sock.recv_buf_from(&mut response_buffer).await?;
+    debug!("Received upstream {}b response via UDP", len);
+    Ok(response_buffer.freeze())
+}
+
+/// Sends a single request via TCP, prefixed with its length (RFC 7766).
+async fn query_tcp(request_bytes: &[u8], upstream: &str) -> anyhow::Result<Bytes> {
+    let mut stream = TcpStream::connect(upstream).await?;
+    stream
+        .write_u16(u16::try_from(request_bytes.len())?)
+        .await?;
+    stream.write_all(request_bytes).await?;
+
+    let len = stream.read_u16().await? as usize;
+    let mut response_buffer = vec![0u8; len];
+    stream.read_exact(&mut response_buffer).await?;
+    debug!("Received upstream {}b response via TCP", len);
+    Ok(Bytes::from(response_buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const REQUEST: &[u8] = &[
+        0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x7a, 0x7a,
+        0x7a, 0x02, 0x61, 0x61, 0x00, 0x00, 0x01, 0x00, 0x01,
+    ];
+
+    #[tokio::test]
+    async fn retries_truncated_responses_via_tcp() {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(upstream).await.unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            let (len, addr) = udp.recv_from(&mut buffer).await.unwrap();
+            // Reply with TC set and no answers
+            let mut response = buffer[..len].to_vec();
+            response[2] = 0x83;
+            udp.send_to(&response, addr).await.unwrap();
+        });
+        tokio::spawn(async move {
+            let (mut stream, _) = tcp.accept().await.unwrap();
+            let len = stream.read_u16().await.unwrap() as usize;
+            let mut request = vec![0u8; len];
+            stream.read_exact(&mut request).await.unwrap();
+
+            // Reply with the question and one answer, dropping the OPT record
+            let mut response = REQUEST.to_vec();
+            response[2] = 0x81;
+            response[7] = 0x01;
+            response.extend_from_slice(&[
+                0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x04, 0x7f, 0x00,
+                0x00, 0x01,
+            ]);
+            stream.write_u16(response.len() as u16).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        });
+
+        let request = DNSRequest::from_bytes(Bytes::from_static(REQUEST)).unwrap();
+        let response = resolve(&request, &upstream.to_string()).await.unwrap();
+        assert!(!response.header.truncation);
+        assert_eq!(1, response.answers.len());
+    }
+
+    #[tokio::test]
+    async fn rejects_responses_to_other_questions() {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream = udp.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            let (_, addr) = udp.recv_from(&mut buffer).await.unwrap();
+            // Reply to a question for zzz.ab instead of zzz.aa
+            let mut response = REQUEST.to_vec();
+            response[2] = 0x81;
+            response[18] = 0x62;
+            udp.send_to(&response, addr).await.unwrap();
+        });
+
+        let request = DNSRequest::from_bytes(Bytes::from_static(REQUEST)).unwrap();
+        let err = resolve(&request, &upstream.to_string()).await.unwrap_err();
+        assert_eq!(
+            Some(&ParseError::QuestionMismatch),
+            err.downcast_ref::<ParseError>()
+        );
+    }
+
+    #[tokio::test]
+    async fn accepts_errors_without_questions() {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream = udp.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            let (_, addr) = udp.recv_from(&mut buffer).await.unwrap();
+            // Reply REFUSED with just the header
+            let mut response = REQUEST[..12].to_vec();
+            response[2] = 0x81;
+            response[3] = 0x05;
+            response[5] = 0x00;
+            udp.send_to(&response, addr).await.unwrap();
+        });
+
+        let request = DNSRequest::from_bytes(Bytes::from_static(REQUEST)).unwrap();
+        let response = resolve(&request, &upstream.to_string()).await.unwrap();
+        assert_eq!(ResponseCode::Refused, response.header.response_code);
+        assert!(response.questions.is_empty());
+    }
 }
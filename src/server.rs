@@ -1,38 +1,180 @@
-use std::net::{IpAddr, SocketAddr};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use anyhow::Context;
-use bytes::{Bytes, BytesMut};
-use log::{debug, info};
-use tokio::net::UdpSocket;
+use anyhow::{anyhow, bail, Context};
+use bytes::Bytes;
+use log::{debug, info, warn};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
 
 use crate::config::ServerConfig;
-use crate::handler::parse_and_handle_request;
+use crate::data::sizes::{MAX_UDP_DATAGRAM_SIZE, REQUEST_HEADER_SIZE};
+use crate::doh;
+use crate::handler::{parse_and_handle_request, Transport};
+use crate::{quic, tls};
+
+/// ALPN protocol of DNS over TLS
+const DOT_ALPN: &[u8] = b"dot";
+/// ALPN protocols of DNS over HTTPS, preferring HTTP/2 (RFC 8484 5.2)
+const DOH_ALPN: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+/// Protocol spoken on accepted stream connections
+#[derive(Clone)]
+enum StreamTransport {
+    /// Plain DNS over TCP
+    Tcp,
+    /// DNS over TLS
+    Tls(TlsAcceptor),
+    /// DNS over HTTPS
+    Https(TlsAcceptor),
+}
 
 pub struct DNSServer {
     config: ServerConfig,
+    stats: Arc<UdpStats>,
+}
+
+/// Counters of datagrams rejected before they are parsed
+#[derive(Debug, Default)]
+pub(crate) struct UdpStats {
+    /// Datagrams larger than the read buffer, which were cut off
+    pub oversized_datagrams: AtomicU64,
+    /// Datagrams too short to contain a header
+    pub truncated_datagrams: AtomicU64,
 }
 
 impl DNSServer {
+    /// Starts one listener task per configured address and transport. Only
+    /// returns once one of them fails. IPv6 addresses are skipped on hosts
+    /// without IPv6 support, so the default config still serves IPv4 there.
     pub async fn listen(self) -> anyhow::Result<()> {
-        let local_addr = SocketAddr::new(IpAddr::from([0u8, 0u8, 0u8, 0u8]), self.config.port);
-        debug!("Binding to UDP: {}", local_addr);
-        let socket = UdpSocket::bind(&local_addr)
-            .await
-            .context("Failed to bind to port")?;
-        let socket = Arc::new(socket);
-        info!("Bound to UDP: {}", local_addr);
+        let config = Arc::new(self.config);
+        let ipv6_available = Socket::new(Domain::IPV6, Type::DGRAM, None).is_ok();
+        let mut listeners = JoinSet::new();
+        for listen_address in &config.listen {
+            let address = listen_address.address;
+            if address.is_ipv6() && !ipv6_available {
+                warn!("Not listening on {}, IPv6 is not available", address);
+                continue;
+            }
+            if listen_address.udp {
+                debug!("Binding to UDP: {}", address);
+                let socket = bind_udp(address)
+                    .with_context(|| format!("Failed to bind to UDP {}", address))?;
+                info!("Bound to UDP: {}", address);
+                listeners.spawn(Self::serve_udp(socket, config.clone(), self.stats.clone()));
+            }
+            if listen_address.tcp {
+                debug!("Binding to TCP: {}", address);
+                let listener = bind_tcp(address)
+                    .with_context(|| format!("Failed to bind to TCP {}", address))?;
+                info!("Bound to TCP: {}", address);
+                listeners.spawn(Self::serve_tcp(
+                    listener,
+                    StreamTransport::Tcp,
+                    config.clone(),
+                ));
+            }
+            if listen_address.tls {
+                let Some(tls) = &config.tls else {
+                    bail!("DNS over TLS on {} requires a certificate", address);
+                };
+                let acceptor = TlsAcceptor::from(tls::server_config(tls, &[DOT_ALPN], false)?);
+                debug!("Binding to TLS: {}", address);
+                let listener = bind_tcp(address)
+                    .with_context(|| format!("Failed to bind to TLS {}", address))?;
+                info!("Bound to TLS: {}", address);
+                listeners.spawn(Self::serve_tcp(
+                    listener,
+                    StreamTransport::Tls(acceptor),
+                    config.clone(),
+                ));
+            }
+            if listen_address.https {
+                let Some(tls) = &config.tls else {
+                    bail!("DNS over HTTPS on {} requires a certificate", address);
+                };
+                let acceptor = TlsAcceptor::from(tls::server_config(tls, DOH_ALPN, false)?);
+                debug!("Binding to HTTPS: {}", address);
+                let listener = bind_tcp(address)
+                    .with_context(|| format!("Failed to bind to HTTPS {}", address))?;
+                info!("Bound to HTTPS: {}", address);
+                listeners.spawn(Self::serve_tcp(
+                    listener,
+                    StreamTransport::Https(acceptor),
+                    config.clone(),
+                ));
+            }
+            if listen_address.quic {
+                let Some(tls) = &config.tls else {
+                    bail!("DNS over QUIC on {} requires a certificate", address);
+                };
+                debug!("Binding to QUIC: {}", address);
+                let socket = bind_udp(address)
+                    .with_context(|| format!("Failed to bind to QUIC {}", address))?;
+                let endpoint = quic::endpoint(socket.into_std()?, tls)?;
+                info!("Bound to QUIC: {}", address);
+                listeners.spawn(quic::serve(endpoint, config.clone()));
+            }
+        }
+        if listeners.is_empty() {
+            bail!("No addresses to listen on");
+        }
+
+        while let Some(result) = listeners.join_next().await {
+            result??;
+        }
+        Ok(())
+    }
 
+    /// Receives datagrams into a single buffer reused for the whole loop,
+    /// each request is copied out of it. The buffer has room for one byte
+    /// more than the largest UDP datagram, so anything filling it completely
+    /// was cut off by the socket and is rejected.
+    async fn serve_udp(
+        socket: UdpSocket,
+        config: Arc<ServerConfig>,
+        stats: Arc<UdpStats>,
+    ) -> anyhow::Result<()> {
+        let socket = Arc::new(socket);
+        let mut read_buffer = vec![0u8; MAX_UDP_DATAGRAM_SIZE + 1];
         loop {
-            let mut read_buffer = BytesMut::new();
             let (len, addr) = socket
-                .recv_buf_from(&mut read_buffer)
+                .recv_from(&mut read_buffer)
                 .await
                 .context("Failed to read data from socket")?;
-            let read_buffer = read_buffer.freeze();
             debug!("Read {}b from {}", len, addr);
+            if len > MAX_UDP_DATAGRAM_SIZE {
+                let count = stats.oversized_datagrams.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(
+                    "Rejected oversized datagram of at least {}b from {}, {} so far",
+                    len, addr, count
+                );
+                continue;
+            }
+            if len < REQUEST_HEADER_SIZE {
+                let count = stats.truncated_datagrams.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(
+                    "Rejected truncated {}b datagram from {}, {} so far",
+                    len, addr, count
+                );
+                continue;
+            }
+
+            let request_bytes = Bytes::copy_from_slice(&read_buffer[..len]);
             let socket = socket.clone();
-            tokio::spawn(async move { Self::handle_request(read_buffer, socket, addr).await });
+            let config = config.clone();
+            tokio::spawn(async move {
+                if let Err(err) = Self::handle_request(request_bytes, socket, addr, &config).await {
+                    warn!("Dropped request from {}: {:?}", addr, err);
+                }
+            });
         }
     }
 
@@ -40,16 +182,439 @@ impl DNSServer {
         request_bytes: Bytes,
         socket: Arc<UdpSocket>,
         remote_addr: SocketAddr,
+        config: &ServerConfig,
     ) -> anyhow::Result<()> {
         debug!("Handling request from {:?}", remote_addr);
-        let response = parse_and_handle_request(request_bytes).await?;
-        let response_bytes = response.to_bytes()?;
+        let Some(response_bytes) =
+            parse_and_handle_request(request_bytes, Transport::Udp, config).await?
+        else {
+            return Ok(());
+        };
         socket.send_to(&response_bytes, remote_addr).await?;
         debug!("Done handling request from {:?}", remote_addr);
         Ok(())
     }
 
+    /// Accepts TCP connections up to the configured limit. Connections beyond
+    /// it are closed right away, so clients fall back to other servers.
+    /// Encrypted connections complete their TLS handshake before any
+    /// messages are exchanged (RFC 7858), it has to finish within the idle
+    /// timeout.
+    async fn serve_tcp(
+        listener: TcpListener,
+        transport: StreamTransport,
+        config: Arc<ServerConfig>,
+    ) -> anyhow::Result<()> {
+        let connections = Arc::new(Semaphore::new(config.max_connections));
+        loop {
+            let (stream, addr) = listener
+                .accept()
+                .await
+                .context("Failed to accept TCP connection")?;
+            let Ok(permit) = connections.clone().try_acquire_owned() else {
+                warn!(
+                    "Rejecting TCP connection from {}, too many connections",
+                    addr
+                );
+                continue;
+            };
+            debug!("Accepted TCP connection from {}", addr);
+            let config = config.clone();
+            let transport = transport.clone();
+            tokio::spawn(async move {
+                let result = match transport {
+                    StreamTransport::Tcp => Self::handle_connection(stream, config).await,
+                    StreamTransport::Tls(acceptor) => {
+                        match timeout(config.idle_timeout, acceptor.accept(stream)).await {
+                            Ok(Ok(stream)) => Self::handle_connection(stream, config).await,
+                            Ok(Err(err)) => Err(anyhow::Error::from(err).context("TLS handshake")),
+                            Err(_) => Err(anyhow!("TLS handshake timed out")),
+                        }
+                    }
+                    StreamTransport::Https(acceptor) => {
+                        match timeout(config.idle_timeout, acceptor.accept(stream)).await {
+                            Ok(Ok(stream)) => doh::serve_connection(stream, config).await,
+                            Ok(Err(err)) => Err(anyhow::Error::from(err).context("TLS handshake")),
+                            Err(_) => Err(anyhow!("TLS handshake timed out")),
+                        }
+                    }
+                };
+                if let Err(err) = result {
+                    warn!("Closed TCP connection from {}: {:?}", addr, err);
+                }
+                drop(permit);
+            });
+        }
+    }
+
+    /// Handles DNS messages on a stream, each prefixed with its length as 2
+    /// bytes (RFC 7766). Requests may be pipelined, every request is handled
+    /// concurrently and responses are sent as soon as they are ready, i.e.
+    /// possibly out of order. Only a limited number of requests is pending at
+    /// a time, so a client not reading its responses stops being read from
+    /// and is disconnected after the idle timeout. The connection is closed
+    /// once the client is idle for too long and all pending responses are
+    /// sent.
+    pub(crate) async fn handle_connection<S>(
+        stream: S,
+        config: Arc<ServerConfig>,
+    ) -> anyhow::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (response_sender, mut response_receiver) = mpsc::channel::<Bytes>(16);
+        let pending_requests = Arc::new(Semaphore::new(config.max_pending_requests));
+
+        let read_requests = async move {
+            loop {
+                let permit = timeout(
+                    config.idle_timeout,
+                    pending_requests.clone().acquire_owned(),
+                )
+                .await
+                .context("Timed out waiting for pending requests")??;
+                let Ok(length) = timeout(config.idle_timeout, reader.read_u16()).await else {
+                    debug!("Closing idle TCP connection");
+                    return Ok(());
+                };
+                let length = match length {
+                    Ok(length) => length as usize,
+                    // Clients close their side once they sent all requests
+                    Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                };
+                let mut request_bytes = vec![0u8; length];
+                timeout(config.idle_timeout, reader.read_exact(&mut request_bytes))
+                    .await
+                    .context("Timed out reading request")??;
+
+                let response_sender = response_sender.clone();
+                let config = config.clone();
+                tokio::spawn(async move {
+                    let request_bytes = Bytes::from(request_bytes);
+                    match parse_and_handle_request(request_bytes, Transport::Tcp, &config).await {
+                        Ok(Some(response_bytes)) => {
+                            // Fails only if the connection is already gone
+                            let _ = response_sender.send(response_bytes).await;
+                        }
+                        Ok(None) => {}
+                        Err(err) => warn!("Dropped TCP request: {:?}", err),
+                    }
+                    drop(permit);
+                });
+            }
+        };
+
+        // Ends once the reader and all pending requests dropped their sender
+        let write_responses = async move {
+            while let Some(response_bytes) = response_receiver.recv().await {
+                writer
+                    .write_u16(u16::try_from(response_bytes.len())?)
+                    .await?;
+                writer.write_all(&response_bytes).await?;
+            }
+            writer.shutdown().await?;
+            anyhow::Ok(())
+        };
+
+        tokio::try_join!(read_requests, write_responses)?;
+        Ok(())
+    }
+
     pub fn new(config: ServerConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            stats: Arc::new(UdpStats::default()),
+        }
+    }
+}
+
+/// Binds a UDP socket. IPv6 sockets only receive IPv6 traffic, so that IPv4
+/// and IPv6 can be served separately on the same port.
+fn bind_udp(address: SocketAddr) -> anyhow::Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Binds a TCP listener, see [bind_udp] for IPv6.
+fn bind_tcp(address: SocketAddr) -> anyhow::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpStream;
+    use tokio_rustls::rustls::client::ClientConfig;
+    use tokio_rustls::rustls::crypto::ring::default_provider;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+    use tokio_rustls::rustls::{HandshakeKind, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    use crate::config::TlsConfig;
+    use crate::data::response::DNSResponse;
+    use crate::handler::tests::chaos_request_bytes;
+
+    use super::*;
+
+    fn framed_chaos_request(identification: u16, domain_name: &str) -> Vec<u8> {
+        let bytes = chaos_request_bytes(identification, domain_name);
+        let mut framed = (bytes.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&bytes);
+        framed
+    }
+
+    #[tokio::test]
+    async fn answers_pipelined_requests_on_stream() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let connection = tokio::spawn(DNSServer::handle_connection(
+            server,
+            Arc::new(ServerConfig::default()),
+        ));
+
+        client
+            .write_all(&framed_chaos_request(0x1201, "version.bind"))
+            .await
+            .unwrap();
+        client
+            .write_all(&framed_chaos_request(0x1202, "hostname.bind"))
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+
+        let mut identifications = Vec::new();
+        for _ in 0..2 {
+            let length = client.read_u16().await.unwrap() as usize;
+            let mut response_bytes = vec![0u8; length];
+            client.read_exact(&mut response_bytes).await.unwrap();
+            let response = DNSResponse::from_bytes(Bytes::from(response_bytes)).unwrap();
+            identifications.push(response.header.identification);
+        }
+        identifications.sort();
+        assert_eq!(vec![0x1201, 0x1202], identifications);
+
+        connection.await.unwrap().unwrap();
+        assert_eq!(0, client.read(&mut [0u8; 1]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn closes_idle_connections() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let config = ServerConfig {
+            idle_timeout: Duration::from_millis(10),
+            ..ServerConfig::default()
+        };
+        DNSServer::handle_connection(server, Arc::new(config))
+            .await
+            .unwrap();
+        assert_eq!(0, client.read(&mut [0u8; 1]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn stops_reading_while_too_many_requests_are_pending() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let config = ServerConfig {
+            idle_timeout: Duration::from_millis(100),
+            max_pending_requests: 2,
+            ..ServerConfig::default()
+        };
+        let connection = tokio::spawn(DNSServer::handle_connection(server, Arc::new(config)));
+
+        // Responses are never read, so the server stops reading requests
+        let request = framed_chaos_request(0x1201, "version.bind");
+        let mut written_requests = 0;
+        let write_requests = async {
+            loop {
+                client.write_all(&request).await.unwrap();
+                written_requests += 1;
+            }
+        };
+        assert!(timeout(Duration::from_millis(50), write_requests)
+            .await
+            .is_err());
+        assert!(written_requests < 32);
+
+        let result = timeout(Duration::from_secs(1), connection).await.unwrap();
+        assert!(result.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn closes_connections_beyond_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = ServerConfig {
+            max_connections: 1,
+            ..ServerConfig::default()
+        };
+        tokio::spawn(DNSServer::serve_tcp(
+            listener,
+            StreamTransport::Tcp,
+            Arc::new(config),
+        ));
+
+        let mut first = TcpStream::connect(address).await.unwrap();
+        first
+            .write_all(&framed_chaos_request(0x1201, "version.bind"))
+            .await
+            .unwrap();
+        first.read_u16().await.unwrap();
+
+        let mut second = TcpStream::connect(address).await.unwrap();
+        second
+            .write_all(&framed_chaos_request(0x1202, "version.bind"))
+            .await
+            .unwrap();
+        assert!(second.read_u16().await.is_err());
+
+        // The permit is released once the first connection is closed
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut third = TcpStream::connect(address).await.unwrap();
+        third
+            .write_all(&framed_chaos_request(0x1203, "version.bind"))
+            .await
+            .unwrap();
+        third.read_u16().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn answers_large_datagrams_and_rejects_truncated_ones() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let stats = Arc::new(UdpStats::default());
+        tokio::spawn(DNSServer::serve_udp(
+            socket,
+            Arc::new(ServerConfig::default()),
+            stats.clone(),
+        ));
+
+        // Request with an OPT record padded well beyond the EDNS payload size
+        let mut large_request = chaos_request_bytes(0x1202, "version.bind").to_vec();
+        large_request[11] = 1;
+        large_request.extend_from_slice(&[
+            0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x0f, 0xa4, 0x00, 0x0c, 0x0f,
+            0xa0,
+        ]);
+        large_request.resize(large_request.len() + 4000, 0);
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client
+            .send_to(&[0x12, 0x34, 0x01], server_addr)
+            .await
+            .unwrap();
+        client.send_to(&large_request, server_addr).await.unwrap();
+        client
+            .send_to(&chaos_request_bytes(0x1201, "version.bind"), server_addr)
+            .await
+            .unwrap();
+
+        // Both valid requests are answered after the truncated one was rejected
+        let mut identifications = Vec::new();
+        let mut buffer = [0u8; 512];
+        for _ in 0..2 {
+            let len = client.recv(&mut buffer).await.unwrap();
+            assert!(len > REQUEST_HEADER_SIZE);
+            identifications.push(u16::from_be_bytes([buffer[0], buffer[1]]));
+        }
+        identifications.sort();
+        assert_eq!(vec![0x1201, 0x1202], identifications);
+        assert_eq!(1, stats.truncated_datagrams.load(Ordering::Relaxed));
+        assert_eq!(0, stats.oversized_datagrams.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn binds_ipv4_and_ipv6_on_the_same_port() {
+        let ipv4 = bind_udp("127.0.0.1:0".parse().unwrap()).unwrap();
+        let port = ipv4.local_addr().unwrap().port();
+        let ipv6 = bind_udp(SocketAddr::from((std::net::Ipv6Addr::UNSPECIFIED, port))).unwrap();
+        assert!(ipv6.local_addr().unwrap().is_ipv6());
+
+        let listener = bind_tcp(SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 0))).unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::net::TcpStream::connect(address).await.unwrap();
+        listener.accept().await.unwrap();
+    }
+
+    /// Writes a self-signed certificate for localhost to temporary files.
+    pub(crate) fn test_tls_config(name: &str) -> (TlsConfig, CertificateDer<'static>) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let directory =
+            std::env::temp_dir().join(format!("dns-server-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let tls = TlsConfig {
+            certificate_path: directory.join("cert.pem"),
+            key_path: directory.join("key.pem"),
+        };
+        std::fs::write(&tls.certificate_path, certified.cert.pem()).unwrap();
+        std::fs::write(&tls.key_path, certified.signing_key.serialize_pem()).unwrap();
+        (tls, certified.cert.der().clone())
+    }
+
+    #[tokio::test]
+    async fn answers_requests_over_tls_and_resumes_sessions() {
+        let (tls, certificate) = test_tls_config("dot");
+        let acceptor = TlsAcceptor::from(tls::server_config(&tls, &[DOT_ALPN], false).unwrap());
+        let listener = bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(DNSServer::serve_tcp(
+            listener,
+            StreamTransport::Tls(acceptor),
+            Arc::new(ServerConfig::default()),
+        ));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate).unwrap();
+        let mut client_config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_config.alpn_protocols = vec![DOT_ALPN.to_vec()];
+        let connector = TlsConnector::from(Arc::new(client_config));
+
+        for expected_handshake in [HandshakeKind::Full, HandshakeKind::Resumed] {
+            let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            let server_name = ServerName::try_from("localhost").unwrap();
+            let mut stream = connector.connect(server_name, stream).await.unwrap();
+            assert_eq!(
+                Some(expected_handshake),
+                stream.get_ref().1.handshake_kind()
+            );
+
+            stream
+                .write_all(&framed_chaos_request(0x1201, "version.bind"))
+                .await
+                .unwrap();
+            let length = stream.read_u16().await.unwrap() as usize;
+            let mut response_bytes = vec![0u8; length];
+            stream.read_exact(&mut response_bytes).await.unwrap();
+            let response = DNSResponse::from_bytes(Bytes::from(response_bytes)).unwrap();
+            assert_eq!(0x1201, response.header.identification);
+            assert_eq!(1, response.answers.len());
+            stream.shutdown().await.unwrap();
+        }
     }
 }
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use tokio_rustls::rustls::crypto::ring::{default_provider, Ticketer};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::ServerSessionMemoryCache;
+use tokio_rustls::rustls::ServerConfig;
+
+use crate::config::TlsConfig;
+
+/// Number of sessions kept by the server for resumption without tickets
+const SESSION_CACHE_SIZE: usize = 1024;
+
+/// Builds the TLS configuration of an encrypted transport from the
+/// certificate chain and private key in the PEM files of the config. Clients
+/// may resume sessions via tickets or the session cache of the server, so
+/// reconnecting skips the full handshake. With `early_data`, resumed
+/// sessions may send 0-RTT data, which rustls only allows with the session
+/// cache to limit replays (RFC 8446 8.1), so no tickets are issued.
+pub(crate) fn server_config(
+    tls: &TlsConfig,
+    alpn_protocols: &[&[u8]],
+    early_data: bool,
+) -> anyhow::Result<Arc<ServerConfig>> {
+    let certificates = CertificateDer::pem_file_iter(&tls.certificate_path)
+        .with_context(|| format!("Failed to open {}", tls.certificate_path.display()))?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to read certificates")?;
+    let key = PrivateKeyDer::from_pem_file(&tls.key_path)
+        .with_context(|| format!("Failed to read private key from {}", tls.key_path.display()))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)?;
+    config.session_storage = ServerSessionMemoryCache::new(SESSION_CACHE_SIZE);
+    if early_data {
+        // QUIC only supports 0-RTT without a size limit
+        config.max_early_data_size = u32::MAX;
+    } else {
+        config.ticketer = Ticketer::new()?;
+    }
+    config.alpn_protocols = alpn_protocols
+        .iter()
+        .map(|protocol| protocol.to_vec())
+        .collect();
+    Ok(Arc::new(config))
+}